@@ -1,5 +1,6 @@
 use mellow_ecs::world::World;
 
+#[allow(dead_code)]
 #[derive(Debug)]
 struct Position {
     x: f32,
@@ -69,11 +70,11 @@ fn main() {
         Important,
     ));
 
-    for (_id, pos) in world.query::<&Position>() {
+    for pos in world.query::<&Position>().iter() {
         println!("position {:?}", pos)
     }
 
-    for (_id, (obj, imp)) in world.query::<(&Object, Option<&Important>)>() {
+    for (obj, imp) in world.query::<(&Object, Option<&Important>)>().iter() {
         if imp.is_some() {
             println!("object with color {:?} is important", obj.color);
         } else {
@@ -81,15 +82,21 @@ fn main() {
         }
     }
 
-    for (_id, (obj, _imp)) in world.query::<(&mut Position, &Important)>() {
+    for (obj, _imp) in world.query::<(&mut Position, &Important)>().iter() {
         obj.x += 2.0;
         println!("important object moved");
     }
 
+    world.insert(second, (Important,));
+    world.remove::<(Important,)>(forth);
+
+    let important_count = world.query::<&Important>().iter().count();
+    println!("{} important objects", important_count);
+
     world.del(third);
     world.del(forth);
 
-    let important_count = world.query::<&Important>().count();
+    let important_count = world.query::<&Important>().iter().count();
     println!("{} important objects left", important_count);
 
     world.del(first);
@@ -2,23 +2,105 @@ use std::time::Instant;
 
 use mellow_ecs::world::World;
 
+const ENTITIES: usize = 1000000;
+const RUNS: u32 = 10;
+
+struct Position(f32, f32);
+struct Velocity(f32, f32);
+
+fn bench(name: &str, mut f: impl FnMut()) {
+    let start_time = Instant::now();
+    (0..RUNS).for_each(|_| f());
+    let duration = (Instant::now() - start_time) / RUNS;
+    println!(
+        "{} in {} ({:.2} ns per entity)",
+        name,
+        duration.as_secs_f64(),
+        duration.as_nanos() as f64 / ENTITIES as f64
+    );
+}
+
 fn main() {
     let mut world = World::default();
 
     let start_time = Instant::now();
 
-    (0..1000000).for_each(|i| {
-        world.spawn((i,));
+    (0..ENTITIES).for_each(|i| {
+        world.spawn((i as i32,));
     });
 
     let duration = Instant::now() - start_time;
     println!("checkpoint in {}", duration.as_secs_f64());
 
     let mut sum: i32 = 0;
-    for (_, num) in world.query::<&i32>() {
+    for num in world.query::<&i32>().iter() {
         sum = sum.wrapping_add(*num);
     }
 
     let duration = Instant::now() - start_time;
     println!("finished in {} with sum {}", duration.as_secs_f64(), sum);
+
+    let mut world = World::default();
+    (0..ENTITIES).for_each(|i| {
+        world.spawn((Position(i as f32, 0.0), Velocity(1.0, 2.0)));
+    });
+
+    bench("query", || {
+        for (pos, vel) in world.query::<(&mut Position, &Velocity)>().iter() {
+            pos.0 += vel.0;
+            pos.1 += vel.1;
+        }
+    });
+
+    bench("query for_each", || {
+        world
+            .query::<(&mut Position, &Velocity)>()
+            .iter()
+            .for_each(|(pos, vel)| {
+                pos.0 += vel.0;
+                pos.1 += vel.1;
+            });
+    });
+
+    bench("query chunks", || {
+        world
+            .query::<(&mut Position, &Velocity)>()
+            .for_each_chunk(|_, (positions, velocities)| {
+                positions
+                    .iter_mut()
+                    .zip(velocities.iter())
+                    .for_each(|(pos, vel)| {
+                        pos.0 += vel.0;
+                        pos.1 += vel.1;
+                    });
+            });
+    });
+
+    let backend = if cfg!(feature = "rayon") {
+        "rayon"
+    } else {
+        "scoped threads"
+    };
+    bench(&format!("parallel query ({})", backend), || {
+        world
+            .par_query::<(&mut Position, &Velocity)>()
+            .for_each(|(pos, vel)| {
+                pos.0 += vel.0;
+                pos.1 += vel.1;
+            });
+    });
+
+    let mut state = world.query_state::<(&mut Position, &Velocity), ()>();
+    bench("cached query", || {
+        for (pos, vel) in state.iter(&world).iter() {
+            pos.0 += vel.0;
+            pos.1 += vel.1;
+        }
+    });
+
+    let mut sum = 0.0;
+    for pos in world.query::<&Position>().iter() {
+        sum += pos.1;
+    }
+    println!("position sum {}", sum);
 }
@@ -1,14 +1,15 @@
-use std::{
-    any::TypeId,
-    ptr::{addr_of, NonNull},
-};
+use std::{any::TypeId, mem::ManuallyDrop, ptr::NonNull};
 
 use crate::store::ItemType;
 
 pub trait Bundle {
     fn type_id() -> TypeId;
+    /// Panics if the bundle contains a component type more than once.
     fn for_each_type(f: impl FnMut(&ItemType));
-    fn get_components(self, f: impl FnMut(NonNull<u8>, &ItemType));
+    /// Hands a pointer to every component to `f`. The bundle is never
+    /// dropped here, so its components can be moved out through them.
+    fn get_components(bundle: &mut ManuallyDrop<Self>, f: impl FnMut(NonNull<u8>, &ItemType));
+    unsafe fn from_components(f: impl FnMut(&ItemType) -> NonNull<u8>) -> Self;
 }
 
 macro_rules! tuple_impl {
@@ -20,12 +21,27 @@ macro_rules! tuple_impl {
             }
 
             fn for_each_type(mut f: impl FnMut(&ItemType)) {
-                $(f(&ItemType::of::<$name>());)*
+                let types = [$(ItemType::of::<$name>()),*];
+                types.iter().enumerate().for_each(|(idx, typ)| {
+                    assert!(
+                        !types[..idx].contains(typ),
+                        "component {} appears more than once in the bundle",
+                        typ.name
+                    );
+                    f(typ);
+                });
             }
 
-            fn get_components(self, mut f: impl FnMut(NonNull<u8>, &ItemType)) {
-                let ($($name,)+) = self;
-                $(f(unsafe { NonNull::new_unchecked(addr_of!($name) as *mut u8) }, &ItemType::of::<$name>());)*
+            fn get_components(
+                bundle: &mut ManuallyDrop<Self>,
+                mut f: impl FnMut(NonNull<u8>, &ItemType),
+            ) {
+                let ($($name,)+) = &mut **bundle;
+                $(f(NonNull::from($name).cast(), &ItemType::of::<$name>());)*
+            }
+
+            unsafe fn from_components(mut f: impl FnMut(&ItemType) -> NonNull<u8>) -> Self {
+                ($(f(&ItemType::of::<$name>()).as_ptr().cast::<$name>().read(),)*)
             }
         }
     };
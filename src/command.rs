@@ -0,0 +1,169 @@
+use std::{
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+    ptr,
+    sync::Arc,
+};
+
+use crate::{
+    bundle::Bundle,
+    entity::{AbandonedIds, EntityId},
+    world::{World, WorldId},
+};
+
+/// Records structural changes to be made to a world later, see
+/// [`World::apply`]. Useful while the world is borrowed, e.g. by a query.
+///
+/// Commands are stored back to back in a single byte buffer, so recording
+/// one doesn't allocate unless the buffer has to grow.
+#[derive(Default)]
+pub struct Commands {
+    // the world that spawned entities reserved their ids from, if any
+    world_id: Option<WorldId>,
+    // handed the spawned ids if the commands are dropped without being
+    // applied, so the world can free them again
+    spawned: Vec<EntityId>,
+    abandoned: Option<Arc<AbandonedIds>>,
+    metas: Vec<CommandMeta>,
+    bytes: Vec<MaybeUninit<u8>>,
+}
+
+struct CommandMeta {
+    offset: usize,
+    apply: unsafe fn(*const MaybeUninit<u8>, &mut World),
+    drop: unsafe fn(*const MaybeUninit<u8>),
+}
+
+trait Command {
+    fn apply(self, world: &mut World);
+}
+
+impl Commands {
+    pub fn len(&self) -> usize {
+        self.metas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metas.is_empty()
+    }
+
+    /// Spawns an entity with the bundle, returning its id right away. The
+    /// commands can then only be applied to the given world. If they are
+    /// dropped instead, the entity is despawned the next time the world is
+    /// changed.
+    pub fn spawn<B: Bundle>(&mut self, world: &World, bundle: B) -> EntityId {
+        assert!(
+            *self.world_id.get_or_insert(world.id()) == world.id(),
+            "commands used with a different world"
+        );
+
+        let entity_id = world.reserve_entity();
+        self.abandoned
+            .get_or_insert_with(|| world.entities.abandoned());
+        self.spawned.push(entity_id);
+        self.push(Insert { entity_id, bundle });
+        entity_id
+    }
+
+    pub fn insert<B: Bundle>(&mut self, entity_id: EntityId, bundle: B) {
+        self.push(Insert { entity_id, bundle });
+    }
+
+    /// Removes the bundle from the entity, dropping its components.
+    pub fn remove<B: Bundle>(&mut self, entity_id: EntityId) {
+        self.push(Remove::<B> {
+            entity_id,
+            _marker: PhantomData,
+        });
+    }
+
+    pub fn despawn(&mut self, entity_id: EntityId) {
+        self.push(Despawn { entity_id });
+    }
+
+    fn push<C: Command>(&mut self, command: C) {
+        unsafe fn apply<C: Command>(ptr: *const MaybeUninit<u8>, world: &mut World) {
+            ptr.cast::<C>().read_unaligned().apply(world);
+        }
+
+        unsafe fn drop<C: Command>(ptr: *const MaybeUninit<u8>) {
+            ptr.cast::<C>().read_unaligned();
+        }
+
+        let offset = self.bytes.len();
+        self.metas.push(CommandMeta {
+            offset,
+            apply: apply::<C>,
+            drop: drop::<C>,
+        });
+
+        // commands are packed without padding, hence the unaligned accesses
+        self.bytes.reserve(mem::size_of::<C>());
+        unsafe {
+            let dst = self.bytes.as_mut_ptr().add(offset);
+            ptr::write_unaligned(dst.cast::<C>(), command);
+            self.bytes.set_len(offset + mem::size_of::<C>());
+        }
+    }
+
+    pub(crate) fn apply(mut self, world: &mut World) {
+        assert!(
+            self.world_id.is_none_or(|world_id| world_id == world.id()),
+            "commands used with a different world"
+        );
+
+        // taken out first, so a panicking command leaks the remaining ones
+        // instead of having them dropped twice
+        self.spawned.clear();
+        let metas = mem::take(&mut self.metas);
+        let bytes = mem::take(&mut self.bytes);
+        metas.iter().for_each(|meta| unsafe {
+            (meta.apply)(bytes.as_ptr().add(meta.offset), world);
+        });
+    }
+}
+
+impl Drop for Commands {
+    fn drop(&mut self) {
+        if let Some(abandoned) = &self.abandoned {
+            abandoned.extend(self.spawned.drain(..));
+        }
+
+        let metas = mem::take(&mut self.metas);
+        metas.iter().for_each(|meta| unsafe {
+            (meta.drop)(self.bytes.as_ptr().add(meta.offset));
+        });
+    }
+}
+
+struct Insert<B> {
+    entity_id: EntityId,
+    bundle: B,
+}
+
+impl<B: Bundle> Command for Insert<B> {
+    fn apply(self, world: &mut World) {
+        world.insert(self.entity_id, self.bundle);
+    }
+}
+
+struct Remove<B> {
+    entity_id: EntityId,
+    _marker: PhantomData<B>,
+}
+
+impl<B: Bundle> Command for Remove<B> {
+    fn apply(self, world: &mut World) {
+        world.remove::<B>(self.entity_id);
+    }
+}
+
+struct Despawn {
+    entity_id: EntityId,
+}
+
+impl Command for Despawn {
+    fn apply(self, world: &mut World) {
+        world.del(self.entity_id);
+    }
+}
@@ -1,33 +1,215 @@
-use std::collections::HashMap;
+use std::{
+    hash::{Hash, Hasher},
+    mem,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+};
 
-use crate::{hasher::BuildNoHasher, tables::TableId};
+use crate::tables::TableId;
 
 #[derive(Default)]
 pub struct Entities {
-    next_id: usize,
-    tables: HashMap<EntityId, TableId, BuildNoHasher<EntityId>>,
+    metas: Vec<EntityMeta>,
+    free: Vec<u32>,
+    reserved: AtomicUsize,
+    abandoned: Arc<AbandonedIds>,
 }
 
 impl Entities {
     pub fn alloc(&mut self) -> EntityId {
-        assert!(self.next_id < usize::MAX);
-        let id = EntityId(self.next_id);
-        self.next_id += 1;
-        id
+        debug_assert!(self.pending() == 0, "reserved entities weren't flushed");
+
+        let index = if let Some(index) = self.free.pop() {
+            index
+        } else {
+            assert!(self.metas.len() < u32::MAX as usize);
+            self.metas.push(EntityMeta::default());
+            (self.metas.len() - 1) as u32
+        };
+
+        let meta = &mut self.metas[index as usize];
+        meta.alive = true;
+
+        EntityId {
+            index,
+            generation: meta.generation,
+        }
+    }
+
+    /// Reserves an id without needing exclusive access. It only becomes a
+    /// live entity once [`Entities::flush`] is called, which has to happen
+    /// before any other entity is allocated or deleted.
+    pub fn reserve(&self) -> EntityId {
+        // the ids are the ones the following allocations would hand out,
+        // first from the end of the free list and then new slots
+        let n = self.reserved.fetch_add(1, Ordering::Relaxed);
+        if let Some(free_idx) = self.free.len().checked_sub(n + 1) {
+            let index = self.free[free_idx];
+            EntityId {
+                index,
+                generation: self.metas[index as usize].generation,
+            }
+        } else {
+            let index = self.metas.len() + n - self.free.len();
+            assert!(index < u32::MAX as usize);
+            EntityId {
+                index: index as u32,
+                generation: 0,
+            }
+        }
+    }
+
+    /// A handle for giving up reserved ids, which are then collected by
+    /// [`Entities::take_abandoned`].
+    pub fn abandoned(&self) -> Arc<AbandonedIds> {
+        self.abandoned.clone()
+    }
+
+    /// Takes the ids given up since the last call. They may have been
+    /// flushed in the meantime, or not be alive anymore.
+    pub fn take_abandoned(&mut self) -> Vec<EntityId> {
+        // checked first so the common case doesn't lock
+        if self.abandoned.len.load(Ordering::Relaxed) == 0 {
+            return Vec::new();
+        }
+
+        let mut ids = self.abandoned.lock();
+        self.abandoned.len.store(0, Ordering::Relaxed);
+        mem::take(&mut *ids)
+    }
+
+    /// How many reserved ids haven't been flushed yet.
+    pub fn pending(&self) -> usize {
+        self.reserved.load(Ordering::Relaxed)
+    }
+
+    /// Allocates every reserved id, placing it at the location `f` returns.
+    pub fn flush(&mut self, mut f: impl FnMut(EntityId) -> (TableId, usize)) {
+        let reserved = mem::take(self.reserved.get_mut());
+        (0..reserved).for_each(|_| {
+            let id = self.alloc();
+            let (table_id, column_idx) = f(id);
+            self.set_location(id, table_id, column_idx);
+        });
+    }
+
+    pub fn del(&mut self, id: EntityId) -> bool {
+        debug_assert!(self.pending() == 0, "reserved entities weren't flushed");
+
+        if !self.contains(id) {
+            return false;
+        }
+
+        let meta = &mut self.metas[id.index as usize];
+        meta.alive = false;
+        meta.location = None;
+
+        // a slot whose generation would wrap around is retired instead of
+        // recycled, so a stale id can never become valid again
+        if let Some(generation) = meta.generation.checked_add(1) {
+            meta.generation = generation;
+            self.free.push(id.index);
+        }
+
+        true
+    }
+
+    pub fn clear(&mut self) {
+        (0..self.metas.len() as u32).for_each(|index| {
+            let meta = &self.metas[index as usize];
+            if meta.alive {
+                self.del(EntityId {
+                    index,
+                    generation: meta.generation,
+                });
+            }
+        });
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.metas
+            .get(id.index as usize)
+            .is_some_and(|meta| meta.alive && meta.generation == id.generation)
     }
 
-    pub fn del(&mut self, id: EntityId) {
-        self.tables.remove(&id);
+    pub fn set_location(&mut self, id: EntityId, table_id: TableId, column_idx: usize) {
+        if self.contains(id) {
+            self.metas[id.index as usize].location = Some((table_id, column_idx));
+        }
     }
 
-    pub fn set_table_id(&mut self, id: EntityId, table_id: TableId) {
-        self.tables.insert(id, table_id);
+    pub fn location(&self, id: EntityId) -> Option<(TableId, usize)> {
+        if self.contains(id) {
+            self.metas[id.index as usize].location
+        } else {
+            None
+        }
     }
 
     pub fn table_id(&self, id: EntityId) -> Option<TableId> {
-        self.tables.get(&id).copied()
+        self.location(id).map(|(table_id, _)| table_id)
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct EntityId(usize);
+/// Reserved ids that will never be spawned, handed back by owners that
+/// can't reach the entities anymore, e.g. dropped commands.
+#[derive(Default)]
+pub struct AbandonedIds {
+    len: AtomicUsize,
+    ids: Mutex<Vec<EntityId>>,
+}
+
+impl AbandonedIds {
+    pub fn extend(&self, ids: impl IntoIterator<Item = EntityId>) {
+        let mut guard = self.lock();
+        guard.extend(ids);
+        self.len.store(guard.len(), Ordering::Relaxed);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<EntityId>> {
+        self.ids.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(Default)]
+struct EntityMeta {
+    generation: u32,
+    alive: bool,
+    location: Option<(TableId, usize)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn to_bits(&self) -> u64 {
+        (self.generation as u64) << 32 | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+// hashed as a single integer so it works with `NoHasher`
+impl Hash for EntityId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.to_bits());
+    }
+}
@@ -1,9 +1,11 @@
 #![allow(clippy::missing_safety_doc)]
 
 pub mod bundle;
+pub mod command;
 pub mod entity;
 pub mod hasher;
 pub mod query;
+pub mod resource;
 pub mod store;
 pub mod tables;
 pub mod world;
@@ -1,69 +1,489 @@
 use std::{
-    iter::{self, Filter, Once, Peekable},
+    borrow::Cow,
+    error::Error,
+    fmt,
     marker::PhantomData,
-    slice::Iter,
+    ops::{Deref, DerefMut},
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
+#[cfg(not(feature = "rayon"))]
+use std::{sync::atomic::AtomicUsize, thread};
+
 use crate::{
     entity::{Entities, EntityId},
-    store::{ItemType, Stores},
-    tables::{Table, Tables},
+    store::{BorrowError, ItemType, StoreId, Stores},
+    tables::{Table, TableId, Tables},
+    world::{World, WorldId},
 };
 
 pub trait Query {
-    fn for_each_type(f: impl FnMut(&ItemType, bool, bool));
-    unsafe fn from_components(f: impl FnMut(&ItemType) -> Option<*mut u8>) -> Self;
+    /// The ids of the columns the query reads from a matching table.
+    type Columns: Copy + Send + Sync;
+    /// The base pointers of those columns, resolved once per table.
+    type Fetch;
+    /// What the query yields for a row, borrowing the world for `'w`.
+    type Item<'w>;
+    /// Contiguous rows of a table, see [`FullQuery::for_each_chunk`].
+    type Slice<'s>;
+
+    /// Reports every component the query borrows and whether it is borrowed
+    /// mutably.
+    fn for_each_type(f: impl FnMut(&ItemType, bool));
+    /// Returns whether the query can fetch the entities of the table.
+    fn matches_table(table: &Table) -> bool;
+    /// Looks up the columns of a matching table.
+    fn columns(table: &Table) -> Self::Columns;
+    /// Resolves the columns of the table to pointers to their first row.
+    unsafe fn fetch(stores: &Stores, table: &Table, columns: &Self::Columns) -> Self::Fetch;
+    /// Fetches the query from a row of the fetched table.
+    unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w>;
+    /// Fetches `len` rows of the fetched table starting at `column_idx`.
+    unsafe fn slice<'s>(fetch: &Self::Fetch, column_idx: usize, len: usize) -> Self::Slice<'s>;
+}
+
+impl Query for EntityId {
+    type Columns = ();
+    type Fetch = *const EntityId;
+    type Item<'w> = EntityId;
+    type Slice<'s> = &'s [EntityId];
+
+    fn for_each_type(_f: impl FnMut(&ItemType, bool)) {}
+
+    fn matches_table(_table: &Table) -> bool {
+        true
+    }
+
+    fn columns(_table: &Table) -> Self::Columns {}
+
+    unsafe fn fetch(_stores: &Stores, table: &Table, _columns: &Self::Columns) -> Self::Fetch {
+        table.entities().as_ptr()
+    }
+
+    unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w> {
+        *fetch.add(column_idx)
+    }
+
+    unsafe fn slice<'s>(fetch: &Self::Fetch, column_idx: usize, len: usize) -> Self::Slice<'s> {
+        slice::from_raw_parts(fetch.add(column_idx), len)
+    }
 }
 
 impl<T: 'static + Send + Sync> Query for &T {
-    fn for_each_type(mut f: impl FnMut(&ItemType, bool, bool)) {
-        f(&ItemType::of::<T>(), false, false);
+    type Columns = StoreId;
+    type Fetch = *const T;
+    type Item<'w> = &'w T;
+    type Slice<'s> = &'s [T];
+
+    fn for_each_type(mut f: impl FnMut(&ItemType, bool)) {
+        f(&ItemType::of::<T>(), false);
+    }
+
+    fn matches_table(table: &Table) -> bool {
+        table.has_column(&ItemType::of::<T>())
     }
 
-    unsafe fn from_components(mut f: impl FnMut(&ItemType) -> Option<*mut u8>) -> Self {
-        &*f(&ItemType::of::<T>()).unwrap().cast::<T>()
+    fn columns(table: &Table) -> Self::Columns {
+        table.column(&ItemType::of::<T>()).unwrap()
+    }
+
+    unsafe fn fetch(stores: &Stores, _table: &Table, columns: &Self::Columns) -> Self::Fetch {
+        stores.get(*columns).get_unchecked(0).cast::<T>()
+    }
+
+    unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w> {
+        &*fetch.add(column_idx)
+    }
+
+    unsafe fn slice<'s>(fetch: &Self::Fetch, column_idx: usize, len: usize) -> Self::Slice<'s> {
+        slice::from_raw_parts(fetch.add(column_idx), len)
     }
 }
 
+/// Marks every fetched row as changed.
 impl<T: 'static + Send + Sync> Query for &mut T {
-    fn for_each_type(mut f: impl FnMut(&ItemType, bool, bool)) {
-        f(&ItemType::of::<T>(), true, false);
+    type Columns = StoreId;
+    type Fetch = MutFetch<T>;
+    type Item<'w> = &'w mut T;
+    type Slice<'s> = &'s mut [T];
+
+    fn for_each_type(mut f: impl FnMut(&ItemType, bool)) {
+        f(&ItemType::of::<T>(), true);
+    }
+
+    fn matches_table(table: &Table) -> bool {
+        table.has_column(&ItemType::of::<T>())
+    }
+
+    fn columns(table: &Table) -> Self::Columns {
+        table.column(&ItemType::of::<T>()).unwrap()
+    }
+
+    unsafe fn fetch(stores: &Stores, _table: &Table, columns: &Self::Columns) -> Self::Fetch {
+        MutFetch::new(stores, *columns)
+    }
+
+    unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w> {
+        (*fetch.changed_ticks.add(column_idx)).store(fetch.change_tick, Ordering::Relaxed);
+        &mut *fetch.ptr.add(column_idx)
+    }
+
+    unsafe fn slice<'s>(fetch: &Self::Fetch, column_idx: usize, len: usize) -> Self::Slice<'s> {
+        fetch.slice(column_idx, len)
+    }
+}
+
+/// A mutable reference to a component that only marks it as changed when
+/// it is written through.
+pub struct Mut<'a, T> {
+    value: &'a mut T,
+    changed_tick: &'a AtomicU64,
+    change_tick: u64,
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
     }
+}
 
-    unsafe fn from_components(mut f: impl FnMut(&ItemType) -> Option<*mut u8>) -> Self {
-        &mut *f(&ItemType::of::<T>()).unwrap().cast::<T>()
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.changed_tick.store(self.change_tick, Ordering::Relaxed);
+        self.value
     }
 }
 
-impl<T: 'static + Send + Sync> Query for Option<&T> {
-    fn for_each_type(mut f: impl FnMut(&ItemType, bool, bool)) {
-        f(&ItemType::of::<T>(), false, true);
+/// Slices mark every row they cover as changed.
+impl<T: 'static + Send + Sync> Query for Mut<'_, T> {
+    type Columns = StoreId;
+    type Fetch = MutFetch<T>;
+    type Item<'w> = Mut<'w, T>;
+    type Slice<'s> = &'s mut [T];
+
+    fn for_each_type(mut f: impl FnMut(&ItemType, bool)) {
+        f(&ItemType::of::<T>(), true);
+    }
+
+    fn matches_table(table: &Table) -> bool {
+        table.has_column(&ItemType::of::<T>())
     }
 
-    unsafe fn from_components(mut f: impl FnMut(&ItemType) -> Option<*mut u8>) -> Self {
-        f(&ItemType::of::<T>()).map(|ptr| &*ptr.cast::<T>())
+    fn columns(table: &Table) -> Self::Columns {
+        table.column(&ItemType::of::<T>()).unwrap()
     }
+
+    unsafe fn fetch(stores: &Stores, _table: &Table, columns: &Self::Columns) -> Self::Fetch {
+        MutFetch::new(stores, *columns)
+    }
+
+    unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w> {
+        Mut {
+            value: &mut *fetch.ptr.add(column_idx),
+            changed_tick: &*fetch.changed_ticks.add(column_idx),
+            change_tick: fetch.change_tick,
+        }
+    }
+
+    unsafe fn slice<'s>(fetch: &Self::Fetch, column_idx: usize, len: usize) -> Self::Slice<'s> {
+        fetch.slice(column_idx, len)
+    }
+}
+
+#[doc(hidden)]
+pub struct MutFetch<T> {
+    ptr: *mut T,
+    changed_ticks: *const AtomicU64,
+    change_tick: u64,
 }
 
-impl<T: 'static + Send + Sync> Query for Option<&mut T> {
-    fn for_each_type(mut f: impl FnMut(&ItemType, bool, bool)) {
-        f(&ItemType::of::<T>(), true, true);
+impl<T> MutFetch<T> {
+    unsafe fn new(stores: &Stores, store_id: StoreId) -> Self {
+        let store = stores.get(store_id);
+        Self {
+            ptr: store.get_unchecked(0).cast::<T>(),
+            changed_ticks: store.changed_ticks().as_ptr(),
+            change_tick: stores.change_tick(),
+        }
     }
 
-    unsafe fn from_components(mut f: impl FnMut(&ItemType) -> Option<*mut u8>) -> Self {
-        f(&ItemType::of::<T>()).map(|ptr| &mut *ptr.cast::<T>())
+    unsafe fn slice<'s>(&self, column_idx: usize, len: usize) -> &'s mut [T] {
+        slice::from_raw_parts(self.changed_ticks.add(column_idx), len)
+            .iter()
+            .for_each(|tick| tick.store(self.change_tick, Ordering::Relaxed));
+        slice::from_raw_parts_mut(self.ptr.add(column_idx), len)
+    }
+}
+
+impl<Q: Query> Query for Option<Q> {
+    type Columns = Option<Q::Columns>;
+    type Fetch = Option<Q::Fetch>;
+    type Item<'w> = Option<Q::Item<'w>>;
+    type Slice<'s> = Option<Q::Slice<'s>>;
+
+    fn for_each_type(f: impl FnMut(&ItemType, bool)) {
+        Q::for_each_type(f);
+    }
+
+    fn matches_table(_table: &Table) -> bool {
+        true
+    }
+
+    fn columns(table: &Table) -> Self::Columns {
+        if Q::matches_table(table) {
+            Some(Q::columns(table))
+        } else {
+            None
+        }
+    }
+
+    unsafe fn fetch(stores: &Stores, table: &Table, columns: &Self::Columns) -> Self::Fetch {
+        columns
+            .as_ref()
+            .map(|columns| Q::fetch(stores, table, columns))
+    }
+
+    unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w> {
+        fetch.as_ref().map(|fetch| Q::get(fetch, column_idx))
+    }
+
+    unsafe fn slice<'s>(fetch: &Self::Fetch, column_idx: usize, len: usize) -> Self::Slice<'s> {
+        fetch.as_ref().map(|fetch| Q::slice(fetch, column_idx, len))
+    }
+}
+
+/// Fetches every query of the tuple that matches, as long as at least one
+/// of them does.
+pub struct AnyOf<T>(pub T);
+
+pub trait Filter {
+    /// What the filter needs to check the rows of a matching table.
+    type Fetch;
+
+    /// Returns whether any entity of the table can pass the filter.
+    fn matches_table(table: &Table) -> bool;
+    /// Prepares checking the rows of a matching table.
+    unsafe fn fetch(stores: &Stores, table: &Table) -> Self::Fetch;
+    /// Returns whether a row of the fetched table passes the filter.
+    unsafe fn matches_row(fetch: &Self::Fetch, column_idx: usize) -> bool;
+}
+
+/// Only matches entities that have the component, without borrowing it.
+pub struct With<T>(PhantomData<T>);
+
+impl<T: 'static + Send + Sync> Filter for With<T> {
+    type Fetch = ();
+
+    fn matches_table(table: &Table) -> bool {
+        table.has_column(&ItemType::of::<T>())
+    }
+
+    unsafe fn fetch(_stores: &Stores, _table: &Table) -> Self::Fetch {}
+
+    unsafe fn matches_row(_fetch: &Self::Fetch, _column_idx: usize) -> bool {
+        true
+    }
+}
+
+/// Only matches entities that don't have the component.
+pub struct Without<T>(PhantomData<T>);
+
+impl<T: 'static + Send + Sync> Filter for Without<T> {
+    type Fetch = ();
+
+    fn matches_table(table: &Table) -> bool {
+        !table.has_column(&ItemType::of::<T>())
+    }
+
+    unsafe fn fetch(_stores: &Stores, _table: &Table) -> Self::Fetch {}
+
+    unsafe fn matches_row(_fetch: &Self::Fetch, _column_idx: usize) -> bool {
+        true
+    }
+}
+
+/// Only matches entities whose component was added since the last
+/// [`World::increment_change_tick`].
+pub struct Added<T>(PhantomData<T>);
+
+impl<T: 'static + Send + Sync> Filter for Added<T> {
+    type Fetch = (*const u64, u64);
+
+    fn matches_table(table: &Table) -> bool {
+        table.has_column(&ItemType::of::<T>())
+    }
+
+    unsafe fn fetch(stores: &Stores, table: &Table) -> Self::Fetch {
+        let store_id = table.column(&ItemType::of::<T>()).unwrap();
+        let store = stores.get(store_id);
+        (store.added_ticks().as_ptr(), stores.change_tick())
+    }
+
+    unsafe fn matches_row(fetch: &Self::Fetch, column_idx: usize) -> bool {
+        let (added_ticks, change_tick) = fetch;
+        *added_ticks.add(column_idx) >= *change_tick
+    }
+}
+
+/// Only matches entities whose component was added or changed since
+/// the last [`World::increment_change_tick`].
+pub struct Changed<T>(PhantomData<T>);
+
+impl<T: 'static + Send + Sync> Filter for Changed<T> {
+    type Fetch = (*const AtomicU64, u64);
+
+    fn matches_table(table: &Table) -> bool {
+        table.has_column(&ItemType::of::<T>())
+    }
+
+    unsafe fn fetch(stores: &Stores, table: &Table) -> Self::Fetch {
+        let store_id = table.column(&ItemType::of::<T>()).unwrap();
+        let store = stores.get(store_id);
+        (store.changed_ticks().as_ptr(), stores.change_tick())
+    }
+
+    unsafe fn matches_row(fetch: &Self::Fetch, column_idx: usize) -> bool {
+        let (changed_ticks, change_tick) = fetch;
+        (*changed_ticks.add(column_idx)).load(Ordering::Relaxed) >= *change_tick
+    }
+}
+
+/// Matches entities that pass any of the filters of the tuple.
+pub struct Or<T>(PhantomData<T>);
+
+impl Filter for () {
+    type Fetch = ();
+
+    fn matches_table(_table: &Table) -> bool {
+        true
+    }
+
+    unsafe fn fetch(_stores: &Stores, _table: &Table) -> Self::Fetch {}
+
+    unsafe fn matches_row(_fetch: &Self::Fetch, _column_idx: usize) -> bool {
+        true
     }
 }
 
 macro_rules! tuple_impl {
     ($($name:ident),*) => {
+        #[allow(non_snake_case)]
         impl<$($name: Query),*> Query for ($($name,)*) {
-            fn for_each_type(mut f: impl FnMut(&ItemType, bool, bool)) {
+            type Columns = ($($name::Columns,)*);
+            type Fetch = ($($name::Fetch,)*);
+            type Item<'w> = ($($name::Item<'w>,)*);
+            type Slice<'s> = ($($name::Slice<'s>,)*);
+
+            fn for_each_type(mut f: impl FnMut(&ItemType, bool)) {
                 $($name::for_each_type(&mut f);)*
             }
 
-            unsafe fn from_components(mut f: impl FnMut(&ItemType) -> Option<*mut u8>) -> Self {
-                ($($name::from_components(&mut f),)*)
+            fn matches_table(table: &Table) -> bool {
+                $($name::matches_table(table))&&*
+            }
+
+            fn columns(table: &Table) -> Self::Columns {
+                ($($name::columns(table),)*)
+            }
+
+            unsafe fn fetch(stores: &Stores, table: &Table, columns: &Self::Columns) -> Self::Fetch {
+                let ($($name,)*) = columns;
+                ($($name::fetch(stores, table, $name),)*)
+            }
+
+            unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w> {
+                let ($($name,)*) = fetch;
+                ($($name::get($name, column_idx),)*)
+            }
+
+            unsafe fn slice<'s>(
+                fetch: &Self::Fetch,
+                column_idx: usize,
+                len: usize,
+            ) -> Self::Slice<'s> {
+                let ($($name,)*) = fetch;
+                ($($name::slice($name, column_idx, len),)*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($name: Query),*> Query for AnyOf<($($name,)*)> {
+            type Columns = ($(Option<$name::Columns>,)*);
+            type Fetch = ($(Option<$name::Fetch>,)*);
+            type Item<'w> = AnyOf<($(Option<$name::Item<'w>>,)*)>;
+            type Slice<'s> = ($(Option<$name::Slice<'s>>,)*);
+
+            fn for_each_type(mut f: impl FnMut(&ItemType, bool)) {
+                $($name::for_each_type(&mut f);)*
+            }
+
+            fn matches_table(table: &Table) -> bool {
+                $($name::matches_table(table))||*
+            }
+
+            fn columns(table: &Table) -> Self::Columns {
+                ($(Option::<$name>::columns(table),)*)
+            }
+
+            unsafe fn fetch(stores: &Stores, table: &Table, columns: &Self::Columns) -> Self::Fetch {
+                let ($($name,)*) = columns;
+                ($(Option::<$name>::fetch(stores, table, $name),)*)
+            }
+
+            unsafe fn get<'w>(fetch: &Self::Fetch, column_idx: usize) -> Self::Item<'w> {
+                let ($($name,)*) = fetch;
+                AnyOf(($(Option::<$name>::get($name, column_idx),)*))
+            }
+
+            unsafe fn slice<'s>(
+                fetch: &Self::Fetch,
+                column_idx: usize,
+                len: usize,
+            ) -> Self::Slice<'s> {
+                let ($($name,)*) = fetch;
+                ($(Option::<$name>::slice($name, column_idx, len),)*)
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($name: Filter),*> Filter for ($($name,)*) {
+            type Fetch = ($($name::Fetch,)*);
+
+            fn matches_table(table: &Table) -> bool {
+                $($name::matches_table(table))&&*
+            }
+
+            unsafe fn fetch(stores: &Stores, table: &Table) -> Self::Fetch {
+                ($($name::fetch(stores, table),)*)
+            }
+
+            unsafe fn matches_row(fetch: &Self::Fetch, column_idx: usize) -> bool {
+                let ($($name,)*) = fetch;
+                $($name::matches_row($name, column_idx))&&*
+            }
+        }
+
+        // only the filters that match the table are checked for its rows
+        #[allow(non_snake_case)]
+        impl<$($name: Filter),*> Filter for Or<($($name,)*)> {
+            type Fetch = ($(Option<$name::Fetch>,)*);
+
+            fn matches_table(table: &Table) -> bool {
+                $($name::matches_table(table))||*
+            }
+
+            unsafe fn fetch(stores: &Stores, table: &Table) -> Self::Fetch {
+                ($($name::matches_table(table).then(|| $name::fetch(stores, table)),)*)
+            }
+
+            unsafe fn matches_row(fetch: &Self::Fetch, column_idx: usize) -> bool {
+                let ($($name,)*) = fetch;
+                $($name
+                    .as_ref()
+                    .is_some_and(|fetch| $name::matches_row(fetch, column_idx)))||*
             }
         }
     };
@@ -96,56 +516,265 @@ tuple_impl!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W,
 tuple_impl!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y);
 tuple_impl!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z);
 
-pub struct FullQuery<'a, Q: Query> {
+/// Caches the tables matched by a query, along with the columns it reads
+/// from each of them. Only tables created since the last use are checked
+/// again, so it's worth keeping around for queries that run often.
+pub struct QueryState<Q: Query, F: Filter = ()> {
+    world_id: WorldId,
+    tables_seen: usize,
+    pub(crate) matches: Vec<(TableId, Q::Columns)>,
+    _marker: PhantomData<F>,
+}
+
+impl<Q: Query, F: Filter> QueryState<Q, F> {
+    pub fn new(world: &World) -> Self {
+        let mut state = Self {
+            world_id: world.id(),
+            tables_seen: 0,
+            matches: Vec::new(),
+            _marker: PhantomData,
+        };
+        state.update(world);
+        state
+    }
+
+    pub fn update(&mut self, world: &World) {
+        assert!(
+            self.world_id == world.id(),
+            "query state used with a different world"
+        );
+
+        world
+            .tables
+            .iter_since(self.tables_seen)
+            .for_each(|(table_id, table)| {
+                if Q::matches_table(table) && F::matches_table(table) {
+                    self.matches.push((table_id, Q::columns(table)));
+                }
+            });
+        self.tables_seen = world.tables.len();
+    }
+
+    /// Panics if a component in `Q` is already borrowed in a conflicting
+    /// way, see [`QueryState::try_iter`] for a non-panicking version.
+    pub fn iter<'a>(&'a mut self, world: &'a World) -> FullQuery<'a, Q, F> {
+        self.try_iter(world).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_iter<'a>(
+        &'a mut self,
+        world: &'a World,
+    ) -> Result<FullQuery<'a, Q, F>, BorrowError> {
+        self.update(world);
+        FullQuery::new(&world.stores, &world.tables, Cow::Borrowed(&self.matches))
+    }
+
+    /// Panics if a component in `Q` is already borrowed in a conflicting
+    /// way, see [`QueryState::try_par_iter`] for a non-panicking version.
+    pub fn par_iter<'a>(&'a mut self, world: &'a World) -> ParQuery<'a, Q, F> {
+        self.try_par_iter(world)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_par_iter<'a>(
+        &'a mut self,
+        world: &'a World,
+    ) -> Result<ParQuery<'a, Q, F>, BorrowError> {
+        self.update(world);
+        ParQuery::new(&world.stores, &world.tables, Cow::Borrowed(&self.matches))
+    }
+}
+
+/// Holds the borrows of a query over the entities of a world, see
+/// [`FullQuery::iter`].
+///
+/// The items borrow the query, so they can't outlive the borrows it holds:
+///
+/// ```compile_fail
+/// # use mellow_ecs::world::World;
+/// # struct A(u32);
+/// let mut world = World::default();
+/// world.spawn((A(0),));
+/// let mut a: Vec<&mut A> = world.query::<&mut A>().iter().collect();
+/// let b: Vec<&mut A> = world.query::<&mut A>().iter().collect();
+/// a[0].0 = b[0].0;
+/// ```
+pub struct FullQuery<'a, Q: Query, F: Filter = ()> {
     stores: &'a Stores,
-    table_iter: Peekable<TableFilter<'a, Q, Iter<'a, Table>>>,
-    column_idx: usize,
+    tables: &'a Tables,
+    matches: Cow<'a, [(TableId, Q::Columns)]>,
     _lock: QueryLock<'a, Q>,
-    _marker: PhantomData<Q>,
+    _marker: PhantomData<F>,
 }
 
-impl<'a, Q: Query> FullQuery<'a, Q> {
-    pub fn new(stores: &'a Stores, tables: &'a Tables) -> Self {
-        Self {
+impl<'a, Q: Query, F: Filter> FullQuery<'a, Q, F> {
+    // the matches have to be the columns `Q` found in these tables, which
+    // only the world and query states can vouch for
+    pub(crate) fn new(
+        stores: &'a Stores,
+        tables: &'a Tables,
+        matches: Cow<'a, [(TableId, Q::Columns)]>,
+    ) -> Result<Self, BorrowError> {
+        Ok(Self {
             stores,
-            table_iter: TableFilter::new(tables.iter()).peekable(),
+            tables,
+            matches,
+            _lock: QueryLock::new(stores)?,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn iter(&mut self) -> QueryIter<'_, Q, F> {
+        QueryIter {
+            stores: self.stores,
+            tables: self.tables,
+            matches: &self.matches,
+            match_idx: 0,
+            fetch: None,
             column_idx: 0,
-            _lock: QueryLock::new(stores),
+            len: 0,
             _marker: PhantomData,
         }
     }
+
+    /// Calls `f` with the ids and components of the matching entities as
+    /// contiguous slices, see [`QueryIter::for_each_chunk`].
+    pub fn for_each_chunk(&mut self, f: impl for<'s> FnMut(&'s [EntityId], Q::Slice<'s>)) {
+        self.iter().for_each_chunk(f);
+    }
 }
 
-impl<'a, Q: Query> Iterator for FullQuery<'a, Q> {
-    type Item = (EntityId, Q);
+impl<'q, Q: Query, F: Filter> IntoIterator for &'q mut FullQuery<'_, Q, F> {
+    type Item = Q::Item<'q>;
+    type IntoIter = QueryIter<'q, Q, F>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(table) = self.table_iter.peek() {
-            if self.column_idx >= table.len() {
-                self.table_iter.next();
-                self.column_idx = 0;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct QueryIter<'q, Q: Query, F: Filter = ()> {
+    stores: &'q Stores,
+    tables: &'q Tables,
+    matches: &'q [(TableId, Q::Columns)],
+    match_idx: usize,
+    fetch: Option<(Q::Fetch, F::Fetch)>,
+    column_idx: usize,
+    len: usize,
+    _marker: PhantomData<F>,
+}
+
+impl<Q: Query, F: Filter> QueryIter<'_, Q, F> {
+    /// Calls `f` with the ids and components of the remaining entities as
+    /// contiguous slices, once per matching table or, if the filter checks
+    /// rows, once per run of consecutive rows that pass it.
+    pub fn for_each_chunk(mut self, mut f: impl for<'s> FnMut(&'s [EntityId], Q::Slice<'s>)) {
+        if let Some((fetch, filter)) = &self.fetch {
+            let table = self.tables.get(self.matches[self.match_idx - 1].0);
+            for_each_run::<F>(filter, self.column_idx, self.len, |start, end| {
+                f(&table.entities()[start..end], unsafe {
+                    Q::slice(fetch, start, end - start)
+                });
+            });
+        }
+
+        while let Some((table_id, columns)) = self.matches.get(self.match_idx) {
+            let table = self.tables.get(*table_id);
+            if !table.is_empty() {
+                let fetch = unsafe { Q::fetch(self.stores, table, columns) };
+                let filter = unsafe { F::fetch(self.stores, table) };
+                for_each_run::<F>(&filter, 0, table.len(), |start, end| {
+                    f(&table.entities()[start..end], unsafe {
+                        Q::slice(&fetch, start, end - start)
+                    });
+                });
             }
+            self.match_idx += 1;
         }
+    }
+}
 
-        if let Some(table) = self.table_iter.peek() {
-            let entity_id = table.get(self.column_idx).unwrap();
-            let components = unsafe {
-                Q::from_components(|typ| {
-                    if let Some(store_id) = table.column(typ) {
-                        let store = self.stores.get(store_id);
-                        store.get(self.column_idx)
-                    } else {
-                        None
+// calls `f` with the bounds of every run of consecutive rows in `start..end`
+// that pass the filter
+fn for_each_run<F: Filter>(
+    filter: &F::Fetch,
+    start: usize,
+    end: usize,
+    mut f: impl FnMut(usize, usize),
+) {
+    let mut column_idx = start;
+    while column_idx < end {
+        let run_start = column_idx;
+        while column_idx < end && unsafe { F::matches_row(filter, column_idx) } {
+            column_idx += 1;
+        }
+
+        if run_start < column_idx {
+            f(run_start, column_idx);
+        } else {
+            column_idx += 1;
+        }
+    }
+}
+
+impl<'q, Q: Query, F: Filter> Iterator for QueryIter<'q, Q, F> {
+    type Item = Q::Item<'q>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            while self.column_idx < self.len {
+                let column_idx = self.column_idx;
+                self.column_idx += 1;
+                if let Some((fetch, filter)) = &self.fetch {
+                    if unsafe { F::matches_row(filter, column_idx) } {
+                        return Some(unsafe { Q::get(fetch, column_idx) });
                     }
-                })
-            };
+                }
+            }
 
-            self.column_idx += 1;
+            let (table_id, columns) = self.matches.get(self.match_idx)?;
+            let table = self.tables.get(*table_id);
+            self.fetch = Some(unsafe {
+                (
+                    Q::fetch(self.stores, table, columns),
+                    F::fetch(self.stores, table),
+                )
+            });
+            self.match_idx += 1;
+            self.column_idx = 0;
+            self.len = table.len();
+        }
+    }
 
-            Some((entity_id, components))
-        } else {
-            None
+    // walks each table in a tight loop, which is what `for_each`, `sum` and
+    // friends end up calling
+    fn fold<B, G: FnMut(B, Self::Item) -> B>(mut self, init: B, mut g: G) -> B {
+        let mut acc = init;
+        if let Some((fetch, filter)) = &self.fetch {
+            acc = (self.column_idx..self.len).fold(acc, |acc, column_idx| unsafe {
+                if F::matches_row(filter, column_idx) {
+                    g(acc, Q::get(fetch, column_idx))
+                } else {
+                    acc
+                }
+            });
+        }
+
+        while let Some((table_id, columns)) = self.matches.get(self.match_idx) {
+            let table = self.tables.get(*table_id);
+            let fetch = unsafe { Q::fetch(self.stores, table, columns) };
+            let filter = unsafe { F::fetch(self.stores, table) };
+            acc = (0..table.len()).fold(acc, |acc, column_idx| unsafe {
+                if F::matches_row(&filter, column_idx) {
+                    g(acc, Q::get(&fetch, column_idx))
+                } else {
+                    acc
+                }
+            });
+            self.match_idx += 1;
         }
+
+        acc
     }
 }
 
@@ -158,113 +787,246 @@ pub struct EntityQuery<'a, Q: Query> {
 }
 
 impl<'a, Q: Query> EntityQuery<'a, Q> {
-    pub fn new(
+    // the row is read unchecked, so the entities have to be the ones that
+    // track these tables
+    pub(crate) fn new(
         stores: &'a Stores,
         tables: &'a Tables,
         entities: &'a Entities,
         entity_id: EntityId,
-    ) -> Self {
-        let table_id = entities.table_id(entity_id);
-        let table = table_id.and_then(|table_id| {
-            let table = tables.get(table_id);
-            TableFilter::<Q, Once<&Table>>::new(iter::once(table)).next()
-        });
-        let column_idx = table.and_then(|table| table.entity_index(entity_id));
+    ) -> Result<Self, BorrowError> {
+        let location = entities
+            .location(entity_id)
+            .map(|(table_id, column_idx)| (tables.get(table_id), column_idx))
+            .filter(|(table, _)| Q::matches_table(table));
 
-        Self {
+        Ok(Self {
             stores,
-            table,
-            column_idx,
-            _lock: QueryLock::new(stores),
+            table: location.map(|(table, _)| table),
+            column_idx: location.map(|(_, column_idx)| column_idx),
+            _lock: QueryLock::new(stores)?,
             _marker: PhantomData,
-        }
+        })
     }
-}
-
-impl<'a, Q: Query> Iterator for EntityQuery<'a, Q> {
-    type Item = Q;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Fetches the query for the entity, or returns `None` if the entity
+    /// doesn't exist or lacks a component of the query.
+    pub fn get(&mut self) -> Option<Q::Item<'_>> {
         self.table
-            .take()
-            .zip(self.column_idx.take())
+            .zip(self.column_idx)
             .map(|(table, column_idx)| unsafe {
-                Q::from_components(|typ| {
-                    if let Some(store_id) = table.column(typ) {
-                        let store = self.stores.get(store_id);
-                        store.get(column_idx)
-                    } else {
-                        None
-                    }
-                })
+                let fetch = Q::fetch(self.stores, table, &Q::columns(table));
+                Q::get(&fetch, column_idx)
             })
     }
 }
 
-pub struct TableFilter<'a, Q: Query, I: Iterator<Item = &'a Table>> {
-    iter: Filter<I, fn(&&Table) -> bool>,
-    _marker: PhantomData<Q>,
+/// Splits the rows of a query into batches that are processed in parallel,
+/// on scoped threads or on the rayon thread pool if the `rayon` feature is
+/// enabled.
+///
+/// Without `rayon`, every call to [`ParQuery::for_each`] spawns its threads
+/// anew, which takes tens of microseconds, so queries over fewer than
+/// [`ParQuery::MIN_PARALLEL_ROWS`] rows stay on the current thread.
+pub struct ParQuery<'a, Q: Query, F: Filter = ()> {
+    stores: &'a Stores,
+    tables: &'a Tables,
+    matches: Cow<'a, [(TableId, Q::Columns)]>,
+    batch_size: usize,
+    _lock: QueryLock<'a, Q>,
+    _marker: PhantomData<F>,
 }
 
-impl<'a, F: Query, I: Iterator<Item = &'a Table>> TableFilter<'a, F, I> {
-    pub fn new(iter: I) -> Self {
-        Self {
-            iter: iter.filter(|table| {
-                if table.is_empty() {
-                    return false;
-                }
+impl<'a, Q: Query, F: Filter> ParQuery<'a, Q, F> {
+    pub const DEFAULT_BATCH_SIZE: usize = 4096;
+    pub const MIN_PARALLEL_ROWS: usize = 16 * 1024;
 
-                let mut ok = true;
-                F::for_each_type(|typ, _, is_opt| {
-                    if !is_opt && !table.has_column(typ) {
-                        ok = false;
-                    }
-                });
-                ok
-            }),
+    // see `FullQuery::new`
+    pub(crate) fn new(
+        stores: &'a Stores,
+        tables: &'a Tables,
+        matches: Cow<'a, [(TableId, Q::Columns)]>,
+    ) -> Result<Self, BorrowError> {
+        Ok(Self {
+            stores,
+            tables,
+            matches,
+            batch_size: Self::DEFAULT_BATCH_SIZE,
+            _lock: QueryLock::new(stores)?,
             _marker: PhantomData,
+        })
+    }
+
+    /// Sets the most rows of a table a single batch covers.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must not be zero");
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn for_each(self, f: impl Fn(Q::Item<'_>) + Send + Sync) {
+        let mut batches = Vec::new();
+        self.matches.iter().for_each(|(table_id, columns)| {
+            let table = self.tables.get(*table_id);
+            (0..table.len()).step_by(self.batch_size).for_each(|start| {
+                let end = table.len().min(start + self.batch_size);
+                batches.push((table, columns, start, end));
+            });
+        });
+
+        let stores = self.stores;
+        let run = |(table, columns, start, end): &(&Table, &Q::Columns, usize, usize)| {
+            let fetch = unsafe { Q::fetch(stores, table, columns) };
+            let filter = unsafe { F::fetch(stores, table) };
+            (*start..*end).for_each(|column_idx| unsafe {
+                if F::matches_row(&filter, column_idx) {
+                    f(Q::get(&fetch, column_idx))
+                }
+            });
+        };
+
+        let rows: usize = batches.iter().map(|(_, _, start, end)| end - start).sum();
+        if cfg!(not(feature = "rayon")) && rows < Self::MIN_PARALLEL_ROWS {
+            batches.iter().for_each(run);
+        } else {
+            par_for_each(&batches, run);
         }
     }
 }
 
-impl<'a, Q: Query, I: Iterator<Item = &'a Table>> Iterator for TableFilter<'a, Q, I> {
-    type Item = &'a Table;
+#[cfg(not(feature = "rayon"))]
+fn par_for_each<T: Sync>(items: &[T], f: impl Fn(&T) + Send + Sync) {
+    let threads = thread::available_parallelism()
+        .map_or(1, |threads| threads.get())
+        .min(items.len());
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
+    if threads <= 1 {
+        items.iter().for_each(f);
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    let work = || {
+        while let Some(item) = items.get(next.fetch_add(1, Ordering::Relaxed)) {
+            f(item);
+        }
+    };
+
+    // the current thread works through batches as well
+    thread::scope(|scope| {
+        (1..threads).for_each(|_| {
+            scope.spawn(work);
+        });
+        work();
+    });
+}
+
+#[cfg(feature = "rayon")]
+fn par_for_each<T: Sync>(items: &[T], f: impl Fn(&T) + Send + Sync) {
+    use rayon::prelude::*;
+
+    items.par_iter().for_each(f);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryEntityError {
+    /// The entity was deleted or never existed.
+    NoSuchEntity(EntityId),
+    /// The entity was requested more than once.
+    AliasedEntity(EntityId),
+    /// The entity lacks a component the query needs.
+    QueryDoesNotMatch(EntityId),
+    /// The query borrows a component in conflicting ways.
+    Borrow(BorrowError),
+}
+
+impl fmt::Display for QueryEntityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuchEntity(id) => write!(f, "entity {:?} does not exist", id),
+            Self::AliasedEntity(id) => write!(f, "entity {:?} was requested more than once", id),
+            Self::QueryDoesNotMatch(id) => {
+                write!(f, "entity {:?} lacks a component of the query", id)
+            }
+            Self::Borrow(err) => err.fmt(f),
+        }
     }
 }
 
-struct QueryLock<'a, Q: Query> {
+impl Error for QueryEntityError {}
+
+pub(crate) struct QueryLock<'a, Q: Query> {
     stores: &'a Stores,
     _marker: PhantomData<Q>,
 }
 
 impl<'a, Q: Query> QueryLock<'a, Q> {
-    pub fn new(stores: &'a Stores) -> Self {
-        Q::for_each_type(|typ, is_mut, _| {
-            if is_mut {
-                stores.acquire_write(typ.id);
-            } else {
-                stores.acquire_read(typ.id);
+    pub fn new(stores: &'a Stores) -> Result<Self, BorrowError> {
+        Self::check_self_conflicts()?;
+
+        let mut acquired = 0;
+        let mut result = Ok(());
+        Q::for_each_type(|typ, is_mut| {
+            if result.is_ok() {
+                result = if is_mut {
+                    stores.acquire_write(typ)
+                } else {
+                    stores.acquire_read(typ)
+                };
+                acquired += result.is_ok() as usize;
             }
         });
 
-        Self {
+        // give back whatever was acquired before the conflict
+        if result.is_err() {
+            let mut idx = 0;
+            Q::for_each_type(|typ, is_mut| {
+                if idx < acquired {
+                    Self::release(stores, typ, is_mut);
+                }
+                idx += 1;
+            });
+        }
+
+        result.map(|_| Self {
             stores,
             _marker: PhantomData,
+        })
+    }
+
+    // a query borrowing a component mutably more than once never succeeds,
+    // whether or not the world has stored the component yet
+    fn check_self_conflicts() -> Result<(), BorrowError> {
+        let mut result = Ok(());
+        let mut idx = 0;
+        Q::for_each_type(|typ, is_mut| {
+            let mut other_idx = 0;
+            Q::for_each_type(|other, other_is_mut| {
+                if result.is_ok() && other_idx < idx && other == typ {
+                    if is_mut {
+                        result = Err(BorrowError::AlreadyBorrowed(typ.name));
+                    } else if other_is_mut {
+                        result = Err(BorrowError::AlreadyBorrowedMut(typ.name));
+                    }
+                }
+                other_idx += 1;
+            });
+            idx += 1;
+        });
+        result
+    }
+
+    fn release(stores: &Stores, typ: &ItemType, is_mut: bool) {
+        if is_mut {
+            stores.release_write(typ);
+        } else {
+            stores.release_read(typ);
         }
     }
 }
 
 impl<'a, Q: Query> Drop for QueryLock<'a, Q> {
     fn drop(&mut self) {
-        Q::for_each_type(|typ, is_mut, _| {
-            if is_mut {
-                self.stores.release_write(typ.id);
-            } else {
-                self.stores.release_read(typ.id);
-            }
-        });
+        Q::for_each_type(|typ, is_mut| Self::release(self.stores, typ, is_mut));
     }
 }
@@ -0,0 +1,236 @@
+use std::{
+    any::{self, Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+    mem,
+    ops::{Deref, DerefMut},
+    thread::{self, ThreadId},
+};
+
+use crate::{
+    hasher::BuildNoHasher,
+    store::{BorrowError, Lock},
+};
+
+/// Singletons that don't belong to any entity, at most one per type.
+/// Resources that aren't `Send` are kept apart and can only be used on the
+/// thread they were inserted on.
+#[derive(Default)]
+pub struct Resources {
+    resources: HashMap<TypeId, Resource, BuildNoHasher<TypeId>>,
+    non_send: HashMap<TypeId, Resource, BuildNoHasher<TypeId>>,
+}
+
+struct Resource {
+    name: &'static str,
+    lock: Lock,
+    // only set for resources that aren't `Send`
+    owner: Option<ThreadId>,
+    // only taken out when the resource is removed
+    value: UnsafeCell<Option<Box<dyn Any>>>,
+}
+
+// resources are either `Send + Sync` or only reached on their owner thread,
+// and their value only through the lock
+unsafe impl Send for Resource {}
+unsafe impl Sync for Resource {}
+
+impl Resource {
+    fn new<R: 'static>(resource: R, owner: Option<ThreadId>) -> Self {
+        Self {
+            name: any::type_name::<R>(),
+            lock: Lock::default(),
+            owner,
+            value: UnsafeCell::new(Some(Box::new(resource))),
+        }
+    }
+
+    fn check_thread(&self) {
+        if let Some(owner) = self.owner {
+            assert!(
+                owner == thread::current().id(),
+                "non-send resource {} used on a thread other than the one it was inserted on",
+                self.name
+            );
+        }
+    }
+
+    fn into_inner<R: 'static>(mut self) -> R {
+        self.check_thread();
+        let value = self.value.get_mut().take().unwrap();
+        *value.downcast::<R>().unwrap()
+    }
+
+    fn get<R: 'static>(&self) -> Result<Res<'_, R>, BorrowError> {
+        self.check_thread();
+        if !self.lock.acquire_read() {
+            return Err(BorrowError::ResourceAlreadyBorrowedMut(self.name));
+        }
+
+        let value = unsafe { &*self.value.get() };
+        Ok(Res {
+            value: value.as_ref().unwrap().downcast_ref().unwrap(),
+            lock: &self.lock,
+        })
+    }
+
+    fn get_mut<R: 'static>(&self) -> Result<ResMut<'_, R>, BorrowError> {
+        self.check_thread();
+        if !self.lock.acquire_write() {
+            return Err(BorrowError::ResourceAlreadyBorrowed(self.name));
+        }
+
+        let value = unsafe { &mut *self.value.get() };
+        Ok(ResMut {
+            value: value.as_mut().unwrap().downcast_mut().unwrap(),
+            lock: &self.lock,
+        })
+    }
+}
+
+impl Drop for Resource {
+    fn drop(&mut self) {
+        let Some(value) = self.value.get_mut().take() else {
+            return;
+        };
+
+        // dropping it here could be unsound, so it's leaked instead, without
+        // panicking again if the thread is unwinding already
+        if self
+            .owner
+            .is_some_and(|owner| owner != thread::current().id())
+        {
+            mem::forget(value);
+            if !thread::panicking() {
+                panic!(
+                    "non-send resource {} dropped on a thread other than the one it was inserted on",
+                    self.name
+                );
+            }
+        }
+    }
+}
+
+impl Resources {
+    /// Returns the resource of the same type that was replaced, if any.
+    pub fn insert<R: 'static + Send + Sync>(&mut self, resource: R) -> Option<R> {
+        self.resources
+            .insert(TypeId::of::<R>(), Resource::new(resource, None))
+            .map(Resource::into_inner)
+    }
+
+    pub fn remove<R: 'static + Send + Sync>(&mut self) -> Option<R> {
+        self.resources
+            .remove(&TypeId::of::<R>())
+            .map(Resource::into_inner)
+    }
+
+    pub fn contains<R: 'static + Send + Sync>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get<R: 'static + Send + Sync>(&self) -> Result<Option<Res<'_, R>>, BorrowError> {
+        self.resources
+            .get(&TypeId::of::<R>())
+            .map(Resource::get)
+            .transpose()
+    }
+
+    pub fn get_mut<R: 'static + Send + Sync>(&self) -> Result<Option<ResMut<'_, R>>, BorrowError> {
+        self.resources
+            .get(&TypeId::of::<R>())
+            .map(Resource::get_mut)
+            .transpose()
+    }
+
+    /// Inserts a resource owned by the current thread, returning the one of
+    /// the same type that was replaced, if any. Panics if that one is owned
+    /// by another thread.
+    pub fn insert_non_send<R: 'static>(&mut self, resource: R) -> Option<R> {
+        let owner = Some(thread::current().id());
+        self.non_send
+            .insert(TypeId::of::<R>(), Resource::new(resource, owner))
+            .map(Resource::into_inner)
+    }
+
+    /// Panics if the resource is owned by another thread.
+    pub fn remove_non_send<R: 'static>(&mut self) -> Option<R> {
+        self.non_send
+            .remove(&TypeId::of::<R>())
+            .map(Resource::into_inner)
+    }
+
+    pub fn contains_non_send<R: 'static>(&self) -> bool {
+        self.non_send.contains_key(&TypeId::of::<R>())
+    }
+
+    /// The thread the resource was inserted on, which is the only one it
+    /// can be used on.
+    pub fn non_send_owner<R: 'static>(&self) -> Option<ThreadId> {
+        self.non_send
+            .get(&TypeId::of::<R>())
+            .and_then(|resource| resource.owner)
+    }
+
+    /// Panics if the resource is owned by another thread.
+    pub fn get_non_send<R: 'static>(&self) -> Result<Option<Res<'_, R>>, BorrowError> {
+        self.non_send
+            .get(&TypeId::of::<R>())
+            .map(Resource::get)
+            .transpose()
+    }
+
+    /// Panics if the resource is owned by another thread.
+    pub fn get_non_send_mut<R: 'static>(&self) -> Result<Option<ResMut<'_, R>>, BorrowError> {
+        self.non_send
+            .get(&TypeId::of::<R>())
+            .map(Resource::get_mut)
+            .transpose()
+    }
+}
+
+/// A shared borrow of a resource, given back when dropped.
+pub struct Res<'a, R> {
+    value: &'a R,
+    lock: &'a Lock,
+}
+
+impl<R> Deref for Res<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<R> Drop for Res<'_, R> {
+    fn drop(&mut self) {
+        self.lock.release_read();
+    }
+}
+
+/// A mutable borrow of a resource, given back when dropped.
+pub struct ResMut<'a, R> {
+    value: &'a mut R,
+    lock: &'a Lock,
+}
+
+impl<R> Deref for ResMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<R> DerefMut for ResMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.value
+    }
+}
+
+impl<R> Drop for ResMut<'_, R> {
+    fn drop(&mut self) {
+        self.lock.release_write();
+    }
+}
@@ -1,10 +1,12 @@
 use std::{
     alloc::{self, Layout},
-    any::TypeId,
+    any::{self, TypeId},
     collections::HashMap,
-    hint,
+    error::Error,
+    fmt,
+    hash::{Hash, Hasher},
     ptr::{self, NonNull},
-    sync::atomic::{AtomicU32, Ordering},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
 };
 
 use crate::hasher::BuildNoHasher;
@@ -13,12 +15,14 @@ use crate::hasher::BuildNoHasher;
 pub struct Stores {
     stores: Vec<Store>,
     locks: HashMap<TypeId, Lock, BuildNoHasher<TypeId>>,
+    change_tick: u64,
 }
 
 impl Stores {
     pub fn create(&mut self, typ: ItemType) -> StoreId {
         let id = self.stores.len();
         self.stores.push(Store::new(typ));
+        self.locks.entry(typ.id).or_default();
         StoreId(id)
     }
 
@@ -34,26 +38,48 @@ impl Stores {
         &mut self.stores[id.0]
     }
 
-    pub fn acquire_read(&self, type_id: TypeId) {
-        if let Some(lock) = self.locks.get(&type_id) {
-            lock.acquire_read()
+    /// The tick that items added or changed right now are marked with.
+    pub fn change_tick(&self) -> u64 {
+        self.change_tick
+    }
+
+    pub fn increment_change_tick(&mut self) {
+        self.change_tick += 1;
+    }
+
+    /// Drops every item and frees the memory of all stores.
+    pub fn clear(&mut self) {
+        self.stores.iter_mut().for_each(|store| {
+            store.clear();
+            store.shrink_to_fit();
+        });
+    }
+
+    // types that were never stored have no data that could be aliased, so
+    // borrowing them always succeeds
+
+    pub fn acquire_read(&self, typ: &ItemType) -> Result<(), BorrowError> {
+        match self.locks.get(&typ.id) {
+            Some(lock) if !lock.acquire_read() => Err(BorrowError::AlreadyBorrowedMut(typ.name)),
+            _ => Ok(()),
         }
     }
 
-    pub fn release_read(&self, type_id: TypeId) {
-        if let Some(lock) = self.locks.get(&type_id) {
+    pub fn release_read(&self, typ: &ItemType) {
+        if let Some(lock) = self.locks.get(&typ.id) {
             lock.release_read()
         }
     }
 
-    pub fn acquire_write(&self, type_id: TypeId) {
-        if let Some(lock) = self.locks.get(&type_id) {
-            lock.acquire_write()
+    pub fn acquire_write(&self, typ: &ItemType) -> Result<(), BorrowError> {
+        match self.locks.get(&typ.id) {
+            Some(lock) if !lock.acquire_write() => Err(BorrowError::AlreadyBorrowed(typ.name)),
+            _ => Ok(()),
         }
     }
 
-    pub fn release_write(&self, type_id: TypeId) {
-        if let Some(lock) = self.locks.get(&type_id) {
+    pub fn release_write(&self, typ: &ItemType) {
+        if let Some(lock) = self.locks.get(&typ.id) {
             lock.release_write()
         }
     }
@@ -62,9 +88,10 @@ impl Stores {
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct StoreId(usize);
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy)]
 pub struct ItemType {
     pub id: TypeId,
+    pub name: &'static str,
     pub layout: Layout,
     pub drop: unsafe fn(*mut u8),
 }
@@ -77,54 +104,100 @@ impl ItemType {
 
         Self {
             id: TypeId::of::<T>(),
+            name: any::type_name::<T>(),
             layout: Layout::new::<T>(),
             drop: drop_ptr::<T>,
         }
     }
 }
 
+impl PartialEq for ItemType {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for ItemType {}
+
+impl Hash for ItemType {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
 pub struct Store {
+    len: usize,
     cap: usize,
     typ: ItemType,
     ptr: NonNull<u8>,
+    // kept apart so marking rows as changed only touches one of them
+    added_ticks: Vec<u64>,
+    changed_ticks: Vec<AtomicU64>,
 }
 
 impl Store {
     pub fn new(typ: ItemType) -> Self {
         Self {
-            cap: 0,
+            len: 0,
+            // zero sized items never need an allocation
+            cap: if typ.layout.size() == 0 {
+                usize::MAX
+            } else {
+                0
+            },
             typ,
-            ptr: NonNull::dangling(),
+            ptr: dangling(&typ),
+            added_ticks: Vec::new(),
+            changed_ticks: Vec::new(),
         }
     }
 
-    pub fn set_capacity(&mut self, cap: usize) {
-        if cap <= self.cap {
+    pub fn item_type(&self) -> &ItemType {
+        &self.typ
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let required = self.len.checked_add(additional).expect("capacity overflow");
+        self.added_ticks.reserve(additional);
+        self.changed_ticks.reserve(additional);
+        if required <= self.cap {
             return;
         }
 
-        let new_cap = cap;
+        self.set_capacity(required.max(self.cap * 2).max(4));
+    }
 
-        let new_layout = unsafe {
-            Layout::from_size_align_unchecked(
-                new_cap * self.typ.layout.size(),
-                self.typ.layout.align(),
-            )
-        };
+    pub fn shrink_to_fit(&mut self) {
+        self.added_ticks.shrink_to_fit();
+        self.changed_ticks.shrink_to_fit();
+        if self.cap > self.len && self.typ.layout.size() != 0 {
+            self.set_capacity(self.len);
+        }
+    }
+
+    fn set_capacity(&mut self, new_cap: usize) {
+        let old_layout = self.array_layout(self.cap);
+        let new_layout = self.array_layout(new_cap);
 
-        let new_ptr = if self.cap == 0 {
+        let new_ptr = if new_cap == 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), old_layout) };
+            dangling(&self.typ).as_ptr()
+        } else if self.cap == 0 {
             unsafe { alloc::alloc(new_layout) }
         } else {
-            unsafe {
-                alloc::realloc(
-                    self.ptr.as_ptr(),
-                    Layout::from_size_align_unchecked(
-                        self.cap * self.typ.layout.size(),
-                        self.typ.layout.align(),
-                    ),
-                    new_cap * self.typ.layout.size(),
-                )
-            }
+            unsafe { alloc::realloc(self.ptr.as_ptr(), old_layout, new_layout.size()) }
         };
 
         self.cap = new_cap;
@@ -134,85 +207,199 @@ impl Store {
         }
     }
 
+    fn array_layout(&self, cap: usize) -> Layout {
+        self.typ
+            .layout
+            .size()
+            .checked_mul(cap)
+            .and_then(|size| Layout::from_size_align(size, self.typ.layout.align()).ok())
+            .expect("capacity overflow")
+    }
+
+    /// Moves the item behind `src` into a new slot at the end of the store,
+    /// along with the ticks it was added and last changed at.
+    pub unsafe fn push(&mut self, src: *const u8, added_tick: u64, changed_tick: u64) -> usize {
+        self.reserve(1);
+        let idx = self.len;
+        unsafe {
+            self.get_unchecked(idx)
+                .copy_from_nonoverlapping(src, self.typ.layout.size())
+        }
+        self.added_ticks.push(added_tick);
+        self.changed_ticks.push(AtomicU64::new(changed_tick));
+        self.len += 1;
+        idx
+    }
+
     pub unsafe fn get_unchecked(&self, idx: usize) -> *mut u8 {
         self.ptr.as_ptr().add(idx * self.typ.layout.size())
     }
 
     pub fn get(&self, idx: usize) -> Option<*mut u8> {
-        if idx < self.cap {
+        if idx < self.len {
             Some(unsafe { self.get_unchecked(idx) })
         } else {
             None
         }
     }
 
+    /// The tick each item was added at.
+    pub fn added_ticks(&self) -> &[u64] {
+        &self.added_ticks
+    }
+
+    /// The tick each item was last changed at. These are atomics since a
+    /// query may mark rows on one thread while a filter reads them on
+    /// another.
+    pub fn changed_ticks(&self) -> &[AtomicU64] {
+        &self.changed_ticks
+    }
+
     pub unsafe fn remove(&mut self, idx: usize) {
-        if idx < self.cap {
+        if idx < self.len {
             unsafe { (self.typ.drop)(self.get_unchecked(idx)) }
-            if idx < self.cap - 1 {
+            self.forget(idx);
+        }
+    }
+
+    pub unsafe fn forget(&mut self, idx: usize) {
+        if idx < self.len {
+            unsafe {
+                ptr::copy(
+                    self.get_unchecked(idx + 1),
+                    self.get_unchecked(idx),
+                    (self.len - idx - 1) * self.typ.layout.size(),
+                );
+            }
+            self.added_ticks.remove(idx);
+            self.changed_ticks.remove(idx);
+            self.len -= 1;
+        }
+    }
+
+    pub unsafe fn swap_remove(&mut self, idx: usize) {
+        if idx < self.len {
+            unsafe { (self.typ.drop)(self.get_unchecked(idx)) }
+            self.swap_forget(idx);
+        }
+    }
+
+    pub unsafe fn swap_forget(&mut self, idx: usize) {
+        if idx < self.len {
+            let last = self.len - 1;
+            if idx < last {
                 unsafe {
-                    ptr::copy(
-                        self.ptr.as_ptr().add(idx * self.typ.layout.size()),
-                        self.ptr.as_ptr().add((idx + 1) * self.typ.layout.size()),
-                        (self.cap - idx - 1) * self.typ.layout.size(),
+                    ptr::copy_nonoverlapping(
+                        self.get_unchecked(last),
+                        self.get_unchecked(idx),
+                        self.typ.layout.size(),
                     );
                 }
             }
+            self.added_ticks.swap_remove(idx);
+            self.changed_ticks.swap_remove(idx);
+            self.len -= 1;
         }
     }
+
+    pub fn clear(&mut self) {
+        // the length is reset first so a panicking destructor leaks the
+        // remaining items instead of dropping them twice
+        let len = self.len;
+        self.len = 0;
+        self.added_ticks.clear();
+        self.changed_ticks.clear();
+        (0..len).for_each(|idx| unsafe { (self.typ.drop)(self.get_unchecked(idx)) });
+    }
 }
 
-#[derive(Default)]
-struct Lock {
-    state: AtomicU32,
+// items are required to be `Send + Sync` by `ItemType::of`
+unsafe impl Send for Store {}
+unsafe impl Sync for Store {}
+
+// a well aligned pointer for stores that haven't allocated yet
+fn dangling(typ: &ItemType) -> NonNull<u8> {
+    unsafe { NonNull::new_unchecked(ptr::without_provenance_mut(typ.layout.align())) }
 }
 
-impl Lock {
-    fn is_read(&self) -> bool {
-        self.state.load(Ordering::Acquire) & !0x8000_0000 != 0
+impl Drop for Store {
+    fn drop(&mut self) {
+        self.clear();
+        if self.typ.layout.size() != 0 && self.cap != 0 {
+            unsafe { alloc::dealloc(self.ptr.as_ptr(), self.array_layout(self.cap)) }
+        }
     }
+}
 
-    fn is_written(&self) -> bool {
-        self.state.load(Ordering::Acquire) & 0x8000_0000 != 0
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorrowError {
+    /// The component is already borrowed, so it can't be borrowed mutably.
+    AlreadyBorrowed(&'static str),
+    /// The component is already borrowed mutably, so it can't be borrowed.
+    AlreadyBorrowedMut(&'static str),
+    /// The resource is already borrowed, so it can't be borrowed mutably.
+    ResourceAlreadyBorrowed(&'static str),
+    /// The resource is already borrowed mutably, so it can't be borrowed.
+    ResourceAlreadyBorrowedMut(&'static str),
+}
 
-    fn acquire_read(&self) {
-        while self.is_written() {
-            hint::spin_loop();
+impl fmt::Display for BorrowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AlreadyBorrowed(name) => write!(f, "component {} already borrowed", name),
+            Self::AlreadyBorrowedMut(name) => {
+                write!(f, "component {} already borrowed mutably", name)
+            }
+            Self::ResourceAlreadyBorrowed(name) => write!(f, "resource {} already borrowed", name),
+            Self::ResourceAlreadyBorrowedMut(name) => {
+                write!(f, "resource {} already borrowed mutably", name)
+            }
         }
-
-        self.state
-            .store(self.state.load(Ordering::Acquire) - 1, Ordering::Release);
     }
+}
 
-    fn release_read(&self) {
-        if self.is_read() {
-            panic!("no read acquired");
-        }
+impl Error for BorrowError {}
 
+const WRITTEN: usize = !(usize::MAX >> 1);
+
+/// Tracks the borrows of one component type. The top bit is set while it is
+/// borrowed mutably, the other bits count the shared borrows.
+#[derive(Default)]
+pub(crate) struct Lock {
+    state: AtomicUsize,
+}
+
+impl Lock {
+    pub(crate) fn acquire_read(&self) -> bool {
         self.state
-            .store(self.state.load(Ordering::Acquire) - 1, Ordering::Release);
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                if state & WRITTEN != 0 {
+                    None
+                } else {
+                    Some(
+                        state
+                            .checked_add(1)
+                            .filter(|state| state & WRITTEN == 0)
+                            .expect("too many borrows"),
+                    )
+                }
+            })
+            .is_ok()
     }
 
-    fn acquire_write(&self) {
-        while self.is_read() {
-            hint::spin_loop();
-        }
-
-        self.state.store(
-            self.state.load(Ordering::Acquire) | 0x8000_0000,
-            Ordering::Release,
-        );
+    pub(crate) fn release_read(&self) {
+        let state = self.state.fetch_sub(1, Ordering::Release);
+        assert!(state & !WRITTEN != 0, "no read acquired");
     }
 
-    fn release_write(&self) {
-        if self.is_written() {
-            panic!("no write acquired");
-        }
+    pub(crate) fn acquire_write(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITTEN, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
 
-        self.state.store(
-            self.state.load(Ordering::Acquire) & !0x8000_0000,
-            Ordering::Release,
-        );
+    pub(crate) fn release_write(&self) {
+        let state = self.state.fetch_and(!WRITTEN, Ordering::Release);
+        assert!(state & WRITTEN != 0, "no write acquired");
     }
 }
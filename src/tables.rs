@@ -9,19 +9,40 @@ use crate::{
 #[derive(Default)]
 pub struct Tables {
     type_ids: HashMap<TypeId, TableId, BuildNoHasher<TypeId>>,
+    archetypes: HashMap<Box<[TypeId]>, TableId>,
     tables: Vec<Table>,
 }
 
 impl Tables {
-    pub fn create(&mut self, type_id: TypeId) -> TableId {
+    pub fn create(
+        &mut self,
+        types: &[ItemType],
+        mut create_store: impl FnMut(&ItemType) -> StoreId,
+    ) -> TableId {
+        let key = archetype_key(types);
+        if let Some(id) = self.archetypes.get(&key) {
+            return *id;
+        }
+
         let id = TableId(self.tables.len());
-        self.tables.push(Table::default());
-        self.type_ids.insert(type_id, id);
+        let mut table = Table::default();
+        types.iter().for_each(|typ| {
+            if !table.has_column(typ) {
+                table.add_column(create_store(typ), *typ);
+            }
+        });
+        self.tables.push(table);
+        self.archetypes.insert(key, id);
         id
     }
 
+    pub fn set_type(&mut self, type_id: TypeId, id: TableId) {
+        self.type_ids.insert(type_id, id);
+    }
+
     pub fn drop(&mut self, id: TableId) {
-        self.type_ids.retain(|_, v| *v == id);
+        self.type_ids.retain(|_, v| *v != id);
+        self.archetypes.retain(|_, v| *v != id);
         self.tables.remove(id.0);
     }
 
@@ -29,6 +50,10 @@ impl Tables {
         self.type_ids.get(&type_id).copied()
     }
 
+    pub fn with_types(&self, types: &[ItemType]) -> Option<TableId> {
+        self.archetypes.get(&archetype_key(types)).copied()
+    }
+
     pub fn get(&self, id: TableId) -> &Table {
         &self.tables[id.0]
     }
@@ -37,16 +62,43 @@ impl Tables {
         &mut self.tables[id.0]
     }
 
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
     pub fn iter(&self) -> std::slice::Iter<'_, Table> {
         self.tables.iter()
     }
 
+    /// Iterates over the tables created at or after the given index, which
+    /// is how many tables there were at some earlier point.
+    pub fn iter_since(&self, start: usize) -> impl Iterator<Item = (TableId, &Table)> {
+        self.tables
+            .iter()
+            .enumerate()
+            .skip(start)
+            .map(|(idx, table)| (TableId(idx), table))
+    }
+
     pub fn iter_mut(&mut self) -> std::slice::IterMut<'_, Table> {
         self.tables.iter_mut()
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+/// Archetypes are identified by their sorted, deduplicated set of component
+/// types, so bundles listing the same components in any order share a table.
+fn archetype_key(types: &[ItemType]) -> Box<[TypeId]> {
+    let mut key: Vec<TypeId> = types.iter().map(|typ| typ.id).collect();
+    key.sort_unstable();
+    key.dedup();
+    key.into_boxed_slice()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct TableId(usize);
 
 #[derive(Default)]
@@ -54,30 +106,57 @@ pub struct Table {
     types: Vec<ItemType>,
     store_ids: Vec<StoreId>,
     entities: Vec<EntityId>,
+    add_edges: HashMap<TypeId, TableId, BuildNoHasher<TypeId>>,
+    remove_edges: HashMap<TypeId, TableId, BuildNoHasher<TypeId>>,
 }
 
 impl Table {
-    pub fn add_column(&mut self, store_id: StoreId, typ: ItemType) {
-        self.store_ids.push(store_id);
-        self.types.push(typ);
+    // columns are kept sorted by type id so lookups can binary search
+    fn add_column(&mut self, store_id: StoreId, typ: ItemType) {
+        let idx = self.types.partition_point(|other| other.id < typ.id);
+        self.store_ids.insert(idx, store_id);
+        self.types.insert(idx, typ);
     }
 
     pub fn column(&self, typ: &ItemType) -> Option<StoreId> {
         self.types
-            .iter()
-            .zip(self.store_ids.iter())
-            .find(|(comp, _)| comp.id == typ.id)
-            .map(|(_, store_id)| *store_id)
+            .binary_search_by(|other| other.id.cmp(&typ.id))
+            .ok()
+            .map(|idx| self.store_ids[idx])
     }
 
     pub fn has_column(&self, typ: &ItemType) -> bool {
         self.column(typ).is_some()
     }
 
+    pub fn types(&self) -> &[ItemType] {
+        &self.types
+    }
+
     pub fn columns(&self) -> &[StoreId] {
         &self.store_ids
     }
 
+    /// Returns the cached table reached by inserting the bundle with the
+    /// given type id into this table.
+    pub fn add_edge(&self, bundle_type_id: TypeId) -> Option<TableId> {
+        self.add_edges.get(&bundle_type_id).copied()
+    }
+
+    pub fn set_add_edge(&mut self, bundle_type_id: TypeId, id: TableId) {
+        self.add_edges.insert(bundle_type_id, id);
+    }
+
+    /// Returns the cached table reached by removing the bundle with the
+    /// given type id from this table.
+    pub fn remove_edge(&self, bundle_type_id: TypeId) -> Option<TableId> {
+        self.remove_edges.get(&bundle_type_id).copied()
+    }
+
+    pub fn set_remove_edge(&mut self, bundle_type_id: TypeId, id: TableId) {
+        self.remove_edges.insert(bundle_type_id, id);
+    }
+
     pub fn len(&self) -> usize {
         self.entities.len()
     }
@@ -92,15 +171,26 @@ impl Table {
         idx
     }
 
-    pub fn entity_index(&self, entity_id: EntityId) -> Option<usize> {
-        self.entities.iter().position(|id| *id == entity_id)
+    pub fn entities(&self) -> &[EntityId] {
+        &self.entities
     }
 
     pub fn get(&self, idx: usize) -> Option<EntityId> {
         self.entities.get(idx).copied()
     }
 
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+
     pub fn remove(&mut self, idx: usize) {
         self.entities.remove(idx);
     }
+
+    /// Removes the row by moving the last one into its place, returning the
+    /// entity that was moved if there was one.
+    pub fn swap_remove(&mut self, idx: usize) -> Option<EntityId> {
+        self.entities.swap_remove(idx);
+        self.entities.get(idx).copied()
+    }
 }
@@ -1,74 +1,824 @@
+use std::{
+    any::TypeId,
+    borrow::Cow,
+    collections::HashMap,
+    mem::ManuallyDrop,
+    ops::Deref,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::ThreadId,
+};
+
 use crate::{
     bundle::Bundle,
+    command::Commands,
     entity::{Entities, EntityId},
-    query::{EntityQuery, FullQuery, Query},
-    store::Stores,
-    tables::Tables,
+    hasher::BuildNoHasher,
+    query::{
+        EntityQuery, Filter, FullQuery, ParQuery, Query, QueryEntityError, QueryLock, QueryState,
+    },
+    resource::{Res, ResMut, Resources},
+    store::{BorrowError, ItemType, Stores},
+    tables::{TableId, Tables},
 };
 
 #[derive(Default)]
 pub struct World {
-    entities: Entities,
-    stores: Stores,
-    tables: Tables,
+    id: WorldId,
+    pub(crate) entities: Entities,
+    pub(crate) stores: Stores,
+    pub(crate) tables: Tables,
+    removal_mode: RemovalMode,
+    removed: HashMap<TypeId, Vec<EntityId>, BuildNoHasher<TypeId>>,
+    resources: Resources,
+}
+
+/// Uniquely identifies a world, so state cached for one world can't be used
+/// with another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WorldId(usize);
+
+impl Default for WorldId {
+    fn default() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// How rows are taken out of a table when an entity is deleted or moved to
+/// another table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RemovalMode {
+    /// Moves the last row into the hole, which is constant time but changes
+    /// the iteration order.
+    #[default]
+    Swap,
+    /// Shifts every following row down, keeping the iteration order.
+    Ordered,
 }
 
 impl World {
     pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityId {
-        let entity_id = self.entities.alloc();
+        self.flush();
 
         let table_id = if let Some(table_id) = self.tables.with_type(B::type_id()) {
             table_id
         } else {
-            let table_id = self.tables.create(B::type_id());
-            B::for_each_type(|typ| {
-                let table = self.tables.get_mut(table_id);
-                let store_id = self.stores.create(*typ);
-                table.add_column(store_id, *typ);
-            });
+            let mut types = Vec::new();
+            B::for_each_type(|typ| types.push(*typ));
+            let table_id = self.table_with_types(&types);
+            self.tables.set_type(B::type_id(), table_id);
             table_id
         };
 
+        let entity_id = self.entities.alloc();
+
         let table = self.tables.get_mut(table_id);
         let column_idx = table.push(entity_id);
 
-        bundle.get_components(|ptr, typ| {
+        let change_tick = self.stores.change_tick();
+        B::get_components(&mut ManuallyDrop::new(bundle), |ptr, typ| {
+            if let Some(store_id) = table.column(typ) {
+                let store = self.stores.get_mut(store_id);
+                unsafe { store.push(ptr.as_ptr(), change_tick, change_tick) };
+            }
+        });
+
+        self.entities.set_location(entity_id, table_id, column_idx);
+
+        entity_id
+    }
+
+    pub fn insert<B: Bundle>(&mut self, entity_id: EntityId, bundle: B) -> bool {
+        self.flush();
+        if let Some(location) = self.entities.location(entity_id) {
+            self.insert_at(entity_id, location, bundle);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn remove<B: Bundle>(&mut self, entity_id: EntityId) -> Option<B> {
+        self.flush();
+        let location = self.entities.location(entity_id)?;
+        self.remove_at(entity_id, location)
+            .map(|(bundle, _)| bundle)
+    }
+
+    pub fn del(&mut self, entity_id: EntityId) -> bool {
+        self.flush();
+        if let Some(location) = self.entities.location(entity_id) {
+            self.del_at(entity_id, location)
+        } else {
+            false
+        }
+    }
+
+    /// Inserts the bundle into the entity at the given location, returning
+    /// its new location.
+    fn insert_at<B: Bundle>(
+        &mut self,
+        entity_id: EntityId,
+        (table_id, column_idx): (TableId, usize),
+        bundle: B,
+    ) -> (TableId, usize) {
+        let new_table_id = self.insert_target::<B>(table_id);
+        let column_idx = if new_table_id == table_id {
+            column_idx
+        } else {
+            self.move_entity(entity_id, table_id, column_idx, new_table_id)
+        };
+
+        let change_tick = self.stores.change_tick();
+        let old_table = self.tables.get(table_id);
+        let table = self.tables.get(new_table_id);
+        let mut bundle = ManuallyDrop::new(bundle);
+        B::get_components(&mut bundle, |ptr, typ| {
             if let Some(store_id) = table.column(typ) {
                 let store = self.stores.get_mut(store_id);
-                store.set_capacity(table.len());
                 unsafe {
-                    store
-                        .get_unchecked(column_idx)
-                        .copy_from(ptr.as_ptr(), typ.layout.size())
+                    if old_table.has_column(typ) {
+                        // the replaced value takes the new one's place in the
+                        // bundle
+                        let dst = store.get_unchecked(column_idx);
+                        ptr::swap_nonoverlapping(dst, ptr.as_ptr(), typ.layout.size());
+                        store.changed_ticks()[column_idx].store(change_tick, Ordering::Relaxed);
+                    } else {
+                        store.push(ptr.as_ptr(), change_tick, change_tick);
+                    }
                 }
             }
         });
 
-        self.entities.set_table_id(entity_id, table_id);
+        // every column holds the entity's row by now, so a panicking drop
+        // only leaks the replaced values that come after it
+        B::get_components(&mut bundle, |ptr, typ| {
+            if old_table.has_column(typ) {
+                unsafe { (typ.drop)(ptr.as_ptr()) };
+            }
+        });
 
-        entity_id
+        (new_table_id, column_idx)
     }
 
-    pub fn del(&mut self, entity_id: EntityId) {
-        if let Some(table_id) = self.entities.table_id(entity_id) {
+    /// Removes the bundle from the entity at the given location, returning it
+    /// along with the entity's new location.
+    fn remove_at<B: Bundle>(
+        &mut self,
+        entity_id: EntityId,
+        (table_id, column_idx): (TableId, usize),
+    ) -> Option<(B, (TableId, usize))> {
+        let new_table_id = self.remove_target::<B>(table_id)?;
+
+        let table = self.tables.get(table_id);
+
+        let bundle = unsafe {
+            B::from_components(|typ| {
+                let store = self.stores.get(table.column(typ).unwrap());
+                NonNull::new_unchecked(store.get_unchecked(column_idx))
+            })
+        };
+
+        let column_idx = self.move_entity(entity_id, table_id, column_idx, new_table_id);
+
+        B::for_each_type(|typ| {
+            self.removed.entry(typ.id).or_default().push(entity_id);
+        });
+
+        Some((bundle, (new_table_id, column_idx)))
+    }
+
+    fn del_at(&mut self, entity_id: EntityId, (table_id, column_idx): (TableId, usize)) -> bool {
+        self.tables.get(table_id).types().iter().for_each(|typ| {
+            self.removed.entry(typ.id).or_default().push(entity_id);
+        });
+
+        self.remove_row(table_id, column_idx, true);
+        self.entities.del(entity_id)
+    }
+
+    /// Reserves an id for an entity without needing exclusive access, e.g.
+    /// while iterating a query. The entity is spawned without components
+    /// the next time the world is changed, for example by [`World::apply`].
+    pub fn reserve_entity(&self) -> EntityId {
+        self.entities.reserve()
+    }
+
+    /// Replays the recorded commands in order.
+    pub fn apply(&mut self, commands: Commands) {
+        self.flush();
+        commands.apply(self);
+    }
+
+    // spawns the reserved entities into the table without components
+    fn flush(&mut self) {
+        if self.entities.pending() != 0 {
+            let table_id = self.table_with_types(&[]);
             let table = self.tables.get_mut(table_id);
-            let column_idx = table.entity_index(entity_id).unwrap();
+            self.entities
+                .flush(|entity_id| (table_id, table.push(entity_id)));
+        }
 
-            table.columns().iter().for_each(|store_id| {
-                let store = self.stores.get_mut(*store_id);
-                unsafe { store.remove(column_idx) }
+        // e.g. spawned by commands that were dropped without being applied
+        self.entities
+            .take_abandoned()
+            .into_iter()
+            .for_each(|entity_id| {
+                if let Some(location) = self.entities.location(entity_id) {
+                    self.del_at(entity_id, location);
+                }
             });
-            table.remove(column_idx);
+    }
+
+    pub fn id(&self) -> WorldId {
+        self.id
+    }
 
-            self.entities.del(entity_id);
+    /// Deletes every entity, dropping all components and freeing the memory
+    /// of their columns.
+    pub fn clear(&mut self) {
+        self.flush();
+        self.tables.iter().for_each(|table| {
+            table.types().iter().for_each(|typ| {
+                let removed = self.removed.entry(typ.id).or_default();
+                removed.extend_from_slice(table.entities());
+            });
+        });
+
+        self.tables.iter_mut().for_each(|table| table.clear());
+        self.stores.clear();
+        self.entities.clear();
+    }
+
+    /// The entities that lost the component, by removing it or deleting the
+    /// entity, since the last [`World::increment_change_tick`] or
+    /// [`World::clear_removed`].
+    pub fn removed<T: 'static + Send + Sync>(&self) -> &[EntityId] {
+        self.removed
+            .get(&TypeId::of::<T>())
+            .map_or(&[], |removed| removed)
+    }
+
+    /// Forgets all removals. Starting a new tick does so as well.
+    pub fn clear_removed(&mut self) {
+        self.removed
+            .values_mut()
+            .for_each(|removed| removed.clear());
+    }
+
+    pub fn removal_mode(&self) -> RemovalMode {
+        self.removal_mode
+    }
+
+    pub fn set_removal_mode(&mut self, mode: RemovalMode) {
+        self.removal_mode = mode;
+    }
+
+    pub fn contains(&self, entity_id: EntityId) -> bool {
+        self.entities.contains(entity_id)
+    }
+
+    pub fn has<T: 'static + Send + Sync>(&self, entity_id: EntityId) -> bool {
+        self.entities
+            .table_id(entity_id)
+            .is_some_and(|table_id| self.tables.get(table_id).has_column(&ItemType::of::<T>()))
+    }
+
+    /// Panics if the component is borrowed mutably, see [`World::try_get`]
+    /// for a non-panicking version.
+    pub fn get<T: 'static + Send + Sync>(&self, entity_id: EntityId) -> Option<Ref<'_, T>> {
+        self.try_get(entity_id)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_get<T: 'static + Send + Sync>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<Option<Ref<'_, T>>, BorrowError> {
+        match self.entities.location(entity_id) {
+            Some(location) => self.try_get_at(location),
+            None => Ok(None),
         }
     }
 
-    pub fn query<Q: Query>(&self) -> FullQuery<Q> {
-        FullQuery::new(&self.stores, &self.tables)
+    /// Marks the component as changed.
+    pub fn get_mut<T: 'static + Send + Sync>(&mut self, entity_id: EntityId) -> Option<&mut T> {
+        self.get_mut_at(self.entities.location(entity_id)?)
+    }
+
+    /// The tick that components added or changed right now are marked with.
+    pub fn change_tick(&self) -> u64 {
+        self.stores.change_tick()
+    }
+
+    /// Starts a new tick, usually once per frame. The [`Added`] and
+    /// [`Changed`] filters only match components added or changed since the
+    /// last call, and [`World::removed`] only holds the removals since then.
+    ///
+    /// [`Added`]: crate::query::Added
+    /// [`Changed`]: crate::query::Changed
+    pub fn increment_change_tick(&mut self) {
+        self.stores.increment_change_tick();
+        self.clear_removed();
+    }
+
+    /// Returns a handle for reading the components of a single entity.
+    pub fn entity(&self, entity_id: EntityId) -> Option<EntityRef<'_>> {
+        let location = self.entities.location(entity_id)?;
+        Some(EntityRef {
+            world: self,
+            entity_id,
+            location,
+        })
+    }
+
+    /// Returns a handle for editing a single entity, which keeps track of the
+    /// entity's location between calls.
+    pub fn entity_mut(&mut self, entity_id: EntityId) -> Option<EntityMut<'_>> {
+        self.flush();
+        let location = self.entities.location(entity_id)?;
+        Some(EntityMut {
+            world: self,
+            entity_id,
+            location,
+        })
+    }
+
+    fn try_get_at<T: 'static + Send + Sync>(
+        &self,
+        location: (TableId, usize),
+    ) -> Result<Option<Ref<'_, T>>, BorrowError> {
+        let Some(ptr) = self.component_ptr(location, &ItemType::of::<T>()) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Ref {
+            _lock: QueryLock::new(&self.stores)?,
+            value: unsafe { &*ptr.cast::<T>() },
+        }))
+    }
+
+    fn get_mut_at<T: 'static + Send + Sync>(
+        &mut self,
+        (table_id, column_idx): (TableId, usize),
+    ) -> Option<&mut T> {
+        let store_id = self.tables.get(table_id).column(&ItemType::of::<T>())?;
+        let store = self.stores.get(store_id);
+        store.changed_ticks()[column_idx].store(self.stores.change_tick(), Ordering::Relaxed);
+        Some(unsafe { &mut *store.get_unchecked(column_idx).cast::<T>() })
+    }
+
+    fn component_ptr(
+        &self,
+        (table_id, column_idx): (TableId, usize),
+        typ: &ItemType,
+    ) -> Option<*mut u8> {
+        let store_id = self.tables.get(table_id).column(typ)?;
+        Some(unsafe { self.stores.get(store_id).get_unchecked(column_idx) })
+    }
+
+    /// Stores a resource, returning the one of the same type it replaced.
+    pub fn insert_resource<R: 'static + Send + Sync>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert(resource)
     }
 
-    pub fn query_entity<Q: Query>(&self, entity_id: EntityId) -> EntityQuery<Q> {
+    pub fn remove_resource<R: 'static + Send + Sync>(&mut self) -> Option<R> {
+        self.resources.remove()
+    }
+
+    pub fn contains_resource<R: 'static + Send + Sync>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    /// Panics if the resource is already borrowed mutably, see
+    /// [`World::try_resource`] for a non-panicking version.
+    pub fn resource<R: 'static + Send + Sync>(&self) -> Option<Res<'_, R>> {
+        self.try_resource().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_resource<R: 'static + Send + Sync>(
+        &self,
+    ) -> Result<Option<Res<'_, R>>, BorrowError> {
+        self.resources.get()
+    }
+
+    /// Panics if the resource is already borrowed, see
+    /// [`World::try_resource_mut`] for a non-panicking version.
+    pub fn resource_mut<R: 'static + Send + Sync>(&self) -> Option<ResMut<'_, R>> {
+        self.try_resource_mut()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_resource_mut<R: 'static + Send + Sync>(
+        &self,
+    ) -> Result<Option<ResMut<'_, R>>, BorrowError> {
+        self.resources.get_mut()
+    }
+
+    /// Stores a resource that isn't `Send`, like a window handle. It can only
+    /// be used on the current thread, which is also the only one the world
+    /// can be dropped on from now on.
+    pub fn insert_non_send_resource<R: 'static>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert_non_send(resource)
+    }
+
+    /// Panics if called on a thread other than the one the resource was
+    /// inserted on.
+    pub fn remove_non_send_resource<R: 'static>(&mut self) -> Option<R> {
+        self.resources.remove_non_send()
+    }
+
+    pub fn contains_non_send_resource<R: 'static>(&self) -> bool {
+        self.resources.contains_non_send::<R>()
+    }
+
+    /// The thread the resource was inserted on, so systems using it can be
+    /// scheduled there.
+    pub fn non_send_resource_owner<R: 'static>(&self) -> Option<ThreadId> {
+        self.resources.non_send_owner::<R>()
+    }
+
+    /// Panics if the resource is already borrowed mutably or if called on a
+    /// thread other than the one it was inserted on.
+    pub fn non_send_resource<R: 'static>(&self) -> Option<Res<'_, R>> {
+        self.try_non_send_resource()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Still panics if called on a thread other than the one the resource
+    /// was inserted on.
+    pub fn try_non_send_resource<R: 'static>(&self) -> Result<Option<Res<'_, R>>, BorrowError> {
+        self.resources.get_non_send()
+    }
+
+    /// Panics if the resource is already borrowed or if called on a thread
+    /// other than the one it was inserted on.
+    pub fn non_send_resource_mut<R: 'static>(&self) -> Option<ResMut<'_, R>> {
+        self.try_non_send_resource_mut()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Still panics if called on a thread other than the one the resource
+    /// was inserted on.
+    pub fn try_non_send_resource_mut<R: 'static>(
+        &self,
+    ) -> Result<Option<ResMut<'_, R>>, BorrowError> {
+        self.resources.get_non_send_mut()
+    }
+
+    /// Panics if a component in `Q` is already borrowed in a conflicting
+    /// way, see [`World::try_query`] for a non-panicking version.
+    pub fn query<Q: Query>(&self) -> FullQuery<'_, Q> {
+        self.try_query().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_query<Q: Query>(&self) -> Result<FullQuery<'_, Q>, BorrowError> {
+        self.try_query_filtered()
+    }
+
+    /// Like [`World::query`], but only yields entities whose table passes
+    /// the filter `F`, e.g. `(With<Player>, Without<Frozen>)`.
+    pub fn query_filtered<Q: Query, F: Filter>(&self) -> FullQuery<'_, Q, F> {
+        self.try_query_filtered()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_query_filtered<Q: Query, F: Filter>(
+        &self,
+    ) -> Result<FullQuery<'_, Q, F>, BorrowError> {
+        let state = QueryState::<Q, F>::new(self);
+        FullQuery::new(&self.stores, &self.tables, Cow::Owned(state.matches))
+    }
+
+    /// Like [`World::query`], but iterates in parallel, see [`ParQuery`].
+    pub fn par_query<Q: Query>(&self) -> ParQuery<'_, Q> {
+        self.try_par_query().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_par_query<Q: Query>(&self) -> Result<ParQuery<'_, Q>, BorrowError> {
+        self.try_par_query_filtered()
+    }
+
+    pub fn par_query_filtered<Q: Query, F: Filter>(&self) -> ParQuery<'_, Q, F> {
+        self.try_par_query_filtered()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_par_query_filtered<Q: Query, F: Filter>(
+        &self,
+    ) -> Result<ParQuery<'_, Q, F>, BorrowError> {
+        let state = QueryState::<Q, F>::new(self);
+        ParQuery::new(&self.stores, &self.tables, Cow::Owned(state.matches))
+    }
+
+    /// Creates a cached query, see [`QueryState`].
+    pub fn query_state<Q: Query, F: Filter>(&self) -> QueryState<Q, F> {
+        QueryState::new(self)
+    }
+
+    /// Panics if a component in `Q` is already borrowed in a conflicting
+    /// way, see [`World::try_query_entity`] for a non-panicking version.
+    pub fn query_entity<Q: Query>(&self, entity_id: EntityId) -> EntityQuery<'_, Q> {
+        self.try_query_entity(entity_id)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_query_entity<Q: Query>(
+        &self,
+        entity_id: EntityId,
+    ) -> Result<EntityQuery<'_, Q>, BorrowError> {
         EntityQuery::new(&self.stores, &self.tables, &self.entities, entity_id)
     }
+
+    /// Fetches the query for several distinct entities at once, which allows
+    /// mutable access to the components of all of them at the same time.
+    ///
+    /// The world stays borrowed for as long as the results are alive:
+    ///
+    /// ```compile_fail
+    /// # use mellow_ecs::world::World;
+    /// # struct A(u32);
+    /// let mut world = World::default();
+    /// let e = world.spawn((A(0),));
+    /// let [a] = world.get_many_mut::<&mut A, 1>([e]).unwrap();
+    /// world.del(e);
+    /// a.0 = 5;
+    /// ```
+    pub fn get_many_mut<Q: Query, const N: usize>(
+        &mut self,
+        entity_ids: [EntityId; N],
+    ) -> Result<[Q::Item<'_>; N], QueryEntityError> {
+        // the query may still conflict with itself, e.g. `(&mut T, &T)`
+        QueryLock::<Q>::new(&self.stores).map_err(QueryEntityError::Borrow)?;
+
+        for (idx, entity_id) in entity_ids.iter().enumerate() {
+            let (table_id, _) = self
+                .entities
+                .location(*entity_id)
+                .ok_or(QueryEntityError::NoSuchEntity(*entity_id))?;
+
+            if entity_ids[..idx].contains(entity_id) {
+                return Err(QueryEntityError::AliasedEntity(*entity_id));
+            }
+
+            if !Q::matches_table(self.tables.get(table_id)) {
+                return Err(QueryEntityError::QueryDoesNotMatch(*entity_id));
+            }
+        }
+
+        Ok(entity_ids.map(|entity_id| {
+            let (table_id, column_idx) = self.entities.location(entity_id).unwrap();
+            let table = self.tables.get(table_id);
+            unsafe {
+                let fetch = Q::fetch(&self.stores, table, &Q::columns(table));
+                Q::get(&fetch, column_idx)
+            }
+        }))
+    }
+
+    fn table_with_types(&mut self, types: &[ItemType]) -> TableId {
+        self.tables.create(types, |typ| self.stores.create(*typ))
+    }
+
+    fn insert_target<B: Bundle>(&mut self, table_id: TableId) -> TableId {
+        if let Some(new_table_id) = self.tables.get(table_id).add_edge(B::type_id()) {
+            return new_table_id;
+        }
+
+        let mut types = self.tables.get(table_id).types().to_vec();
+        let mut disjoint = true;
+        B::for_each_type(|typ| {
+            if types.contains(typ) {
+                disjoint = false;
+            } else {
+                types.push(*typ);
+            }
+        });
+
+        let new_table_id = self.table_with_types(&types);
+        self.tables
+            .get_mut(table_id)
+            .set_add_edge(B::type_id(), new_table_id);
+
+        // removing the bundle again only leads back here if it added every
+        // one of its components
+        if disjoint {
+            self.tables
+                .get_mut(new_table_id)
+                .set_remove_edge(B::type_id(), table_id);
+        }
+
+        new_table_id
+    }
+
+    fn remove_target<B: Bundle>(&mut self, table_id: TableId) -> Option<TableId> {
+        if let Some(new_table_id) = self.tables.get(table_id).remove_edge(B::type_id()) {
+            return Some(new_table_id);
+        }
+
+        let mut types = self.tables.get(table_id).types().to_vec();
+        let mut has_all = true;
+        B::for_each_type(|typ| {
+            if let Some(idx) = types.iter().position(|other| other == typ) {
+                types.remove(idx);
+            } else {
+                has_all = false;
+            }
+        });
+
+        if !has_all {
+            return None;
+        }
+
+        let new_table_id = self.table_with_types(&types);
+        self.tables
+            .get_mut(table_id)
+            .set_remove_edge(B::type_id(), new_table_id);
+        self.tables
+            .get_mut(new_table_id)
+            .set_add_edge(B::type_id(), table_id);
+
+        Some(new_table_id)
+    }
+
+    /// Moves the entity's row from one table to another, returning its new
+    /// index. Columns missing from the destination are forgotten, not
+    /// dropped, so their values must have been taken out beforehand.
+    fn move_entity(
+        &mut self,
+        entity_id: EntityId,
+        src_id: TableId,
+        column_idx: usize,
+        dst_id: TableId,
+    ) -> usize {
+        let new_column_idx = self.tables.get_mut(dst_id).push(entity_id);
+
+        let src = self.tables.get(src_id);
+        let dst = self.tables.get(dst_id);
+        src.types()
+            .iter()
+            .zip(src.columns())
+            .for_each(|(typ, store_id)| {
+                if let Some(dst_store_id) = dst.column(typ) {
+                    let store = self.stores.get(*store_id);
+                    unsafe {
+                        let ptr = store.get_unchecked(column_idx);
+                        let added_tick = store.added_ticks()[column_idx];
+                        let changed_tick =
+                            store.changed_ticks()[column_idx].load(Ordering::Relaxed);
+                        self.stores
+                            .get_mut(dst_store_id)
+                            .push(ptr, added_tick, changed_tick);
+                    }
+                }
+            });
+
+        self.remove_row(src_id, column_idx, false);
+        self.entities
+            .set_location(entity_id, dst_id, new_column_idx);
+
+        new_column_idx
+    }
+
+    /// Removes a row from the table and its columns according to the removal
+    /// mode, updating the location of every entity that moved because of it.
+    /// The row's components are only dropped if `drop` is set.
+    fn remove_row(&mut self, table_id: TableId, column_idx: usize, drop: bool) {
+        let mode = self.removal_mode;
+        let table = self.tables.get_mut(table_id);
+
+        table.columns().iter().for_each(|store_id| {
+            let store = self.stores.get_mut(*store_id);
+            unsafe {
+                match (mode, drop) {
+                    (RemovalMode::Swap, true) => store.swap_remove(column_idx),
+                    (RemovalMode::Swap, false) => store.swap_forget(column_idx),
+                    (RemovalMode::Ordered, true) => store.remove(column_idx),
+                    (RemovalMode::Ordered, false) => store.forget(column_idx),
+                }
+            }
+        });
+
+        match mode {
+            RemovalMode::Swap => {
+                if let Some(moved_id) = table.swap_remove(column_idx) {
+                    self.entities.set_location(moved_id, table_id, column_idx);
+                }
+            }
+            RemovalMode::Ordered => {
+                table.remove(column_idx);
+                table.entities()[column_idx..].iter().enumerate().for_each(
+                    |(offset, entity_id)| {
+                        self.entities
+                            .set_location(*entity_id, table_id, column_idx + offset)
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// A shared borrow of a component, see [`World::get`]. Queries can't borrow
+/// the component mutably until it is dropped.
+pub struct Ref<'a, T: 'static + Send + Sync> {
+    value: &'a T,
+    _lock: QueryLock<'a, &'a T>,
+}
+
+impl<T: 'static + Send + Sync> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+/// A read-only handle to a single entity, see [`World::entity`].
+pub struct EntityRef<'a> {
+    world: &'a World,
+    entity_id: EntityId,
+    location: (TableId, usize),
+}
+
+impl<'a> EntityRef<'a> {
+    pub fn id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    /// The types of the entity's components, sorted by type id.
+    pub fn archetype(&self) -> &'a [ItemType] {
+        self.world.tables.get(self.location.0).types()
+    }
+
+    pub fn has<T: 'static + Send + Sync>(&self) -> bool {
+        self.world
+            .tables
+            .get(self.location.0)
+            .has_column(&ItemType::of::<T>())
+    }
+
+    /// Panics if the component is borrowed mutably, see
+    /// [`EntityRef::try_get`] for a non-panicking version.
+    pub fn get<T: 'static + Send + Sync>(&self) -> Option<Ref<'a, T>> {
+        self.try_get().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_get<T: 'static + Send + Sync>(&self) -> Result<Option<Ref<'a, T>>, BorrowError> {
+        self.world.try_get_at(self.location)
+    }
+}
+
+/// A handle for editing a single entity, see [`World::entity_mut`].
+pub struct EntityMut<'a> {
+    world: &'a mut World,
+    entity_id: EntityId,
+    location: (TableId, usize),
+}
+
+impl EntityMut<'_> {
+    pub fn id(&self) -> EntityId {
+        self.entity_id
+    }
+
+    /// The types of the entity's components, sorted by type id.
+    pub fn archetype(&self) -> &[ItemType] {
+        self.world.tables.get(self.location.0).types()
+    }
+
+    pub fn has<T: 'static + Send + Sync>(&self) -> bool {
+        self.world
+            .tables
+            .get(self.location.0)
+            .has_column(&ItemType::of::<T>())
+    }
+
+    // the world is borrowed exclusively, so nothing else can borrow the
+    // component
+    pub fn get<T: 'static + Send + Sync>(&self) -> Option<&T> {
+        let ptr = self
+            .world
+            .component_ptr(self.location, &ItemType::of::<T>())?;
+        Some(unsafe { &*ptr.cast::<T>() })
+    }
+
+    /// Marks the component as changed.
+    pub fn get_mut<T: 'static + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.world.get_mut_at(self.location)
+    }
+
+    pub fn insert<B: Bundle>(&mut self, bundle: B) -> &mut Self {
+        self.location = self.world.insert_at(self.entity_id, self.location, bundle);
+        self
+    }
+
+    pub fn remove<B: Bundle>(&mut self) -> Option<B> {
+        let (bundle, location) = self.world.remove_at(self.entity_id, self.location)?;
+        self.location = location;
+        Some(bundle)
+    }
+
+    pub fn despawn(self) {
+        self.world.del_at(self.entity_id, self.location);
+    }
 }
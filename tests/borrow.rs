@@ -0,0 +1,144 @@
+use mellow_ecs::{entity::EntityId, store::BorrowError, world::World};
+
+struct Position(#[allow(dead_code)] f32);
+struct Velocity(#[allow(dead_code)] f32);
+
+fn world() -> World {
+    let mut world = World::default();
+    world.spawn((Position(0.0), Velocity(1.0)));
+    world
+}
+
+fn position_name() -> &'static str {
+    std::any::type_name::<Position>()
+}
+
+#[test]
+fn shared_borrows_coexist() {
+    let world = world();
+
+    let mut first = world.query::<&Position>();
+    let mut second = world.query::<(&Position, Option<&Position>)>();
+    let mut third =
+        world.query_entity::<&Position>(world.query::<EntityId>().iter().next().unwrap());
+
+    let (a, b, c) = (first.iter().next(), second.iter().next(), third.get());
+    assert!(a.is_some() && b.is_some() && c.is_some());
+}
+
+#[test]
+fn disjoint_mutable_borrows_coexist() {
+    let world = world();
+
+    let positions = world.try_query::<&mut Position>();
+    let velocities = world.try_query::<&mut Velocity>();
+
+    assert!(positions.is_ok());
+    assert!(velocities.is_ok());
+}
+
+#[test]
+fn mut_after_shared_conflicts() {
+    let world = world();
+
+    let _positions = world.query::<&Position>();
+
+    assert_eq!(
+        world.try_query::<&mut Position>().err(),
+        Some(BorrowError::AlreadyBorrowed(position_name()))
+    );
+    assert_eq!(
+        world.try_query::<Option<&mut Position>>().err(),
+        Some(BorrowError::AlreadyBorrowed(position_name()))
+    );
+}
+
+#[test]
+fn shared_after_mut_conflicts() {
+    let world = world();
+
+    let _positions = world.query::<&mut Position>();
+
+    assert_eq!(
+        world.try_query::<&Position>().err(),
+        Some(BorrowError::AlreadyBorrowedMut(position_name()))
+    );
+    assert_eq!(
+        world.try_query::<Option<&Position>>().err(),
+        Some(BorrowError::AlreadyBorrowedMut(position_name()))
+    );
+}
+
+#[test]
+fn mut_after_mut_conflicts() {
+    let world = world();
+
+    let _positions = world.query::<Option<&mut Position>>();
+
+    assert_eq!(
+        world.try_query::<&mut Position>().err(),
+        Some(BorrowError::AlreadyBorrowed(position_name()))
+    );
+}
+
+#[test]
+fn conflicts_within_one_query() {
+    let world = world();
+
+    assert!(world.try_query::<(&mut Position, &Position)>().is_err());
+    assert!(world.try_query::<(&Position, &mut Position)>().is_err());
+    assert!(world.try_query::<(&mut Position, &mut Position)>().is_err());
+    assert!(world
+        .try_query::<(&Velocity, (&mut Position, Option<&Position>))>()
+        .is_err());
+
+    // failed queries give back everything they acquired
+    assert!(world.try_query::<(&mut Position, &mut Velocity)>().is_ok());
+}
+
+#[test]
+fn entity_queries_conflict() {
+    let world = world();
+    let entity = world.query::<EntityId>().iter().next().unwrap();
+
+    let _position = world.query_entity::<&mut Position>(entity);
+
+    assert!(world.try_query_entity::<&Position>(entity).is_err());
+    assert!(world.try_query::<&Position>().is_err());
+    assert!(world.try_query_entity::<&Velocity>(entity).is_ok());
+}
+
+#[test]
+fn borrows_are_released_on_drop() {
+    let world = world();
+
+    let positions = world.query::<&mut Position>();
+    drop(positions);
+    let positions = world.query::<&Position>();
+    drop(positions);
+
+    assert!(world.try_query::<&mut Position>().is_ok());
+}
+
+#[test]
+fn self_conflicts_do_not_depend_on_the_world() {
+    let mut world = world();
+
+    for _ in 0..2 {
+        assert!(world.try_query::<(&mut String, &String)>().is_err());
+        assert!(world.try_query::<(&String, &mut String)>().is_err());
+        assert!(world.try_query::<(&String, Option<&String>)>().is_ok());
+        assert!(world.try_query::<(&mut String, &Position)>().is_ok());
+
+        world.spawn((String::from("stored"),));
+    }
+}
+
+#[test]
+#[should_panic(expected = "already borrowed mutably")]
+fn query_panics_on_conflict() {
+    let world = world();
+
+    let _positions = world.query::<&mut Position>();
+    world.query::<&Position>();
+}
@@ -0,0 +1,105 @@
+use mellow_ecs::{command::Commands, entity::EntityId, world::World};
+
+#[derive(Debug, PartialEq)]
+struct Health(u32);
+#[derive(Debug, PartialEq)]
+struct Bullet(u8);
+struct Stunned;
+
+#[test]
+fn despawn_and_spawn_while_iterating() {
+    let mut world = World::default();
+    let alive = world.spawn((Health(3),));
+    let dead = world.spawn((Health(0),));
+
+    let mut commands = Commands::default();
+    let mut spawned = Vec::new();
+    for (id, health) in world.query::<(EntityId, &Health)>().iter() {
+        if health.0 == 0 {
+            commands.despawn(id);
+        } else {
+            spawned.push(commands.spawn(&world, (Bullet(1), Health(1))));
+        }
+    }
+    assert_eq!(commands.len(), 2);
+    assert!(!world.contains(spawned[0]));
+
+    world.apply(commands);
+    assert!(world.contains(alive));
+    assert!(!world.contains(dead));
+    assert_eq!(world.get::<Bullet>(spawned[0]).as_deref(), Some(&Bullet(1)));
+    assert_eq!(world.query::<&Health>().iter().count(), 2);
+}
+
+#[test]
+fn commands_apply_in_order() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(1),));
+
+    let mut commands = Commands::default();
+    commands.insert(entity, (Stunned,));
+    commands.insert(entity, (Health(2),));
+    commands.remove::<(Stunned,)>(entity);
+    let spawned = commands.spawn(&world, (Bullet(0),));
+    commands.despawn(spawned);
+    world.apply(commands);
+
+    assert_eq!(world.get::<Health>(entity).as_deref(), Some(&Health(2)));
+    assert!(!world.has::<Stunned>(entity));
+    assert!(!world.contains(spawned));
+}
+
+#[test]
+fn reserved_ids_reuse_free_slots() {
+    let mut world = World::default();
+    let first = world.spawn((Health(1),));
+    let second = world.spawn((Health(2),));
+    world.del(first);
+    world.del(second);
+
+    let a = world.reserve_entity();
+    let b = world.reserve_entity();
+    let c = world.reserve_entity();
+    assert_eq!(a.index(), second.index());
+    assert_eq!(b.index(), first.index());
+    assert_ne!(c.index(), a.index());
+    assert_ne!(c.index(), b.index());
+
+    // reserved entities are spawned without components by the next change
+    let d = world.spawn((Health(4),));
+    assert!([a, b, c, d].iter().all(|id| world.contains(*id)));
+    assert!(!world.contains(first));
+    assert_eq!(world.query::<EntityId>().iter().count(), 4);
+    assert!(world.del(a));
+}
+
+#[test]
+#[should_panic(expected = "different world")]
+fn commands_are_tied_to_their_world() {
+    let world = World::default();
+    let mut other = World::default();
+
+    let mut commands = Commands::default();
+    commands.spawn(&world, (Bullet(0),));
+    other.apply(commands);
+}
+
+#[test]
+fn unapplied_spawns_are_despawned() {
+    let mut world = World::default();
+    world.spawn((Health(1),));
+
+    let mut commands = Commands::default();
+    let reserved = commands.spawn(&world, (Health(2),));
+    // the next change flushes the reserved id into an empty entity
+    world.spawn((Health(3),));
+    let pending = commands.spawn(&world, (Health(4),));
+    assert!(world.contains(reserved));
+    drop(commands);
+
+    world.spawn((Health(5),));
+    assert!(!world.contains(reserved));
+    assert!(!world.contains(pending));
+    assert_eq!(world.query::<EntityId>().iter().count(), 3);
+    assert_eq!(world.query::<&Health>().iter().count(), 3);
+}
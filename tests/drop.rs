@@ -0,0 +1,263 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use mellow_ecs::{command::Commands, world::World};
+
+#[derive(Clone, Default)]
+struct Counter(Arc<AtomicUsize>);
+
+impl Counter {
+    fn drops(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+struct Tracked {
+    counter: Counter,
+    _data: String,
+}
+
+impl Tracked {
+    fn new(counter: &Counter) -> Self {
+        Self {
+            counter: counter.clone(),
+            _data: String::from("tracked"),
+        }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        self.counter.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+struct Other(#[allow(dead_code)] Vec<u32>);
+
+// panics when dropped while armed
+struct Fragile(bool);
+
+impl Drop for Fragile {
+    fn drop(&mut self) {
+        assert!(!self.0, "fragile component dropped");
+    }
+}
+
+#[test]
+fn spawn_does_not_drop() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    world.spawn((Tracked::new(&counter),));
+    world.spawn((Tracked::new(&counter), Other(vec![1, 2, 3])));
+
+    assert_eq!(counter.drops(), 0);
+}
+
+#[test]
+fn del_drops_once() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    let first = world.spawn((Tracked::new(&counter), Box::new(1u32)));
+    let second = world.spawn((Tracked::new(&counter), Box::new(2u32)));
+
+    assert!(world.del(first));
+    assert_eq!(counter.drops(), 1);
+    assert!(!world.del(first));
+    assert_eq!(counter.drops(), 1);
+
+    assert!(world.del(second));
+    assert_eq!(counter.drops(), 2);
+}
+
+#[test]
+fn teardown_drops_once() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    (0..100).for_each(|i| {
+        if i % 2 == 0 {
+            world.spawn((Tracked::new(&counter),));
+        } else {
+            world.spawn((Tracked::new(&counter), Other(vec![i])));
+        }
+    });
+    drop(world);
+
+    assert_eq!(counter.drops(), 100);
+}
+
+#[test]
+fn insert_drops_replaced_component() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    let entity = world.spawn((Tracked::new(&counter),));
+    assert!(world.insert(entity, (Other(vec![1]),)));
+    assert_eq!(counter.drops(), 0);
+
+    assert!(world.insert(entity, (Tracked::new(&counter),)));
+    assert_eq!(counter.drops(), 1);
+
+    drop(world);
+    assert_eq!(counter.drops(), 2);
+}
+
+#[test]
+fn panicking_drop_during_insert_keeps_columns_in_sync() {
+    let mut world = World::default();
+    let entity = world.spawn((Fragile(true),));
+
+    let insert = panic::catch_unwind(AssertUnwindSafe(|| {
+        world.insert(entity, (Fragile(false), Box::new(1u32)));
+    }));
+    assert!(insert.is_err());
+    assert_eq!(world.get::<Box<u32>>(entity).as_deref(), Some(&Box::new(1)));
+
+    let other = world.spawn((Fragile(false), Box::new(2u32)));
+    assert_eq!(world.get::<Box<u32>>(other).as_deref(), Some(&Box::new(2)));
+}
+
+#[test]
+fn insert_on_dead_entity_drops_bundle() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    let entity = world.spawn((Other(vec![1]),));
+    world.del(entity);
+
+    assert!(!world.insert(entity, (Tracked::new(&counter),)));
+    assert_eq!(counter.drops(), 1);
+}
+
+#[test]
+fn remove_hands_out_ownership() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    let entity = world.spawn((Tracked::new(&counter), Other(vec![1])));
+    let (tracked,) = world.remove::<(Tracked,)>(entity).unwrap();
+    assert_eq!(counter.drops(), 0);
+
+    drop(tracked);
+    assert_eq!(counter.drops(), 1);
+
+    drop(world);
+    assert_eq!(counter.drops(), 1);
+}
+
+#[test]
+fn moved_rows_keep_their_components() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    let entities: Vec<_> = (0..10)
+        .map(|_| world.spawn((Tracked::new(&counter),)))
+        .collect();
+    entities.iter().step_by(2).for_each(|entity| {
+        world.insert(*entity, (Other(vec![]),));
+    });
+    entities.iter().step_by(3).for_each(|entity| {
+        world.del(*entity);
+    });
+    assert_eq!(counter.drops(), 4);
+
+    drop(world);
+    assert_eq!(counter.drops(), 10);
+}
+
+#[test]
+fn clear_drops_once() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    let entities: Vec<_> = (0..10)
+        .map(|_| world.spawn((Tracked::new(&counter),)))
+        .collect();
+    world.insert(entities[3], (Other(vec![3]),));
+
+    world.clear();
+    assert_eq!(counter.drops(), 10);
+    assert!(entities.iter().all(|entity| !world.contains(*entity)));
+    assert_eq!(world.query::<&Tracked>().iter().count(), 0);
+
+    drop(world);
+    assert_eq!(counter.drops(), 10);
+}
+
+#[test]
+fn world_is_usable_after_clear() {
+    let counter = Counter::default();
+    let mut world = World::default();
+
+    let old = world.spawn((Tracked::new(&counter),));
+    world.clear();
+
+    let new = world.spawn((Tracked::new(&counter),));
+    assert_ne!(old, new);
+    assert!(!world.del(old));
+    assert_eq!(world.query::<&Tracked>().iter().count(), 1);
+
+    drop(world);
+    assert_eq!(counter.drops(), 2);
+}
+
+#[test]
+fn zero_sized_components_are_dropped() {
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Marker;
+
+    impl Drop for Marker {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let mut world = World::default();
+    let entity = world.spawn((Marker,));
+    world.spawn((Marker,));
+    world.spawn((Marker,));
+
+    world.del(entity);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+
+    world.clear();
+    assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn commands_move_components_into_the_world() {
+    let counter = Counter::default();
+    let mut world = World::default();
+    let entity = world.spawn((Other(vec![1]),));
+
+    let mut commands = Commands::default();
+    let spawned = commands.spawn(&world, (Tracked::new(&counter), Other(vec![2])));
+    commands.insert(entity, (Tracked::new(&counter),));
+    commands.remove::<(Tracked,)>(entity);
+    world.apply(commands);
+
+    assert_eq!(counter.drops(), 1);
+    assert!(world.del(spawned));
+    assert_eq!(counter.drops(), 2);
+}
+
+#[test]
+fn unapplied_commands_drop_once() {
+    let counter = Counter::default();
+    let world = World::default();
+
+    let mut commands = Commands::default();
+    commands.spawn(&world, (Tracked::new(&counter), Other(vec![1, 2])));
+    commands.insert(world.reserve_entity(), (Tracked::new(&counter), 7u8));
+    drop(commands);
+
+    assert_eq!(counter.drops(), 2);
+}
@@ -0,0 +1,504 @@
+use mellow_ecs::{
+    entity::EntityId,
+    query::{Added, AnyOf, Changed, Mut, Or, QueryEntityError, With, Without},
+    world::World,
+};
+
+#[derive(Debug, PartialEq)]
+struct Position(i32);
+struct Player;
+struct Frozen;
+struct Enemy;
+struct Neutral;
+#[derive(Debug, PartialEq)]
+struct Sprite(u8);
+#[derive(Debug, PartialEq)]
+struct Mesh(u8);
+
+#[test]
+fn with_and_without_filter_tables() {
+    let mut world = World::default();
+    let player = world.spawn((Position(0), Player));
+    world.spawn((Position(1), Player, Frozen));
+    world.spawn((Position(2),));
+    world.spawn((Position(3), Frozen));
+
+    let mut query =
+        world.query_filtered::<(EntityId, &Position), (With<Player>, Without<Frozen>)>();
+    let players: Vec<_> = query.iter().collect();
+    assert_eq!(players, vec![(player, &Position(0))]);
+
+    let mut frozen: Vec<_> = world
+        .query_filtered::<&Position, With<Frozen>>()
+        .iter()
+        .map(|position| position.0)
+        .collect();
+    frozen.sort();
+    assert_eq!(frozen, vec![1, 3]);
+}
+
+#[test]
+fn filters_do_not_borrow() {
+    let mut world = World::default();
+    world.spawn((Position(0), Frozen));
+
+    let _frozen = world.query::<&mut Frozen>();
+
+    assert_eq!(
+        world
+            .try_query_filtered::<&Position, With<Frozen>>()
+            .unwrap()
+            .iter()
+            .count(),
+        1
+    );
+    assert!(world
+        .try_query_filtered::<&Position, Without<Frozen>>()
+        .is_ok());
+}
+
+#[test]
+fn or_filter_matches_any() {
+    let mut world = World::default();
+    world.spawn((Position(0), Enemy));
+    world.spawn((Position(1), Neutral));
+    world.spawn((Position(2), Enemy, Neutral));
+    world.spawn((Position(3), Player));
+
+    let mut positions: Vec<_> = world
+        .query_filtered::<&Position, Or<(With<Enemy>, With<Neutral>)>>()
+        .iter()
+        .map(|position| position.0)
+        .collect();
+    positions.sort();
+    assert_eq!(positions, vec![0, 1, 2]);
+
+    let mut positions: Vec<_> = world
+        .query_filtered::<&Position, (Or<(With<Enemy>, With<Player>)>, Without<Neutral>)>()
+        .iter()
+        .map(|position| position.0)
+        .collect();
+    positions.sort();
+    assert_eq!(positions, vec![0, 3]);
+}
+
+#[test]
+fn any_of_fetches_existing_components() {
+    let mut world = World::default();
+    world.spawn((Position(0), Sprite(0)));
+    world.spawn((Position(1), Mesh(1)));
+    world.spawn((Position(2), Sprite(2), Mesh(2)));
+    world.spawn((Position(3),));
+
+    let mut query = world.query::<(&Position, AnyOf<(&Sprite, &Mesh)>)>();
+    let mut items: Vec<_> = query
+        .iter()
+        .map(|(position, AnyOf((sprite, mesh)))| (position.0, sprite, mesh))
+        .collect();
+    items.sort_by_key(|(position, _, _)| *position);
+    assert_eq!(
+        items,
+        vec![
+            (0, Some(&Sprite(0)), None),
+            (1, None, Some(&Mesh(1))),
+            (2, Some(&Sprite(2)), Some(&Mesh(2))),
+        ]
+    );
+}
+
+#[test]
+fn any_of_borrows_every_component() {
+    let mut world = World::default();
+    world.spawn((Sprite(0),));
+    world.spawn((Mesh(0),));
+
+    let _meshes = world.query::<&Mesh>();
+
+    assert!(world.try_query::<AnyOf<(&Sprite, &mut Mesh)>>().is_err());
+    assert!(world.try_query::<AnyOf<(&Sprite, &Mesh)>>().is_ok());
+}
+
+#[test]
+fn entity_id_is_a_query_element() {
+    let mut world = World::default();
+    let first = world.spawn((Position(0),));
+    let second = world.spawn((Position(1), Player));
+
+    let mut query = world.query::<(EntityId, &Position)>();
+    let mut items: Vec<_> = query.iter().collect();
+    items.sort_by_key(|(_, position)| position.0);
+    assert_eq!(items, vec![(first, &Position(0)), (second, &Position(1))]);
+
+    let players: Vec<_> = world
+        .query_filtered::<EntityId, With<Player>>()
+        .iter()
+        .collect();
+    assert_eq!(players, vec![second]);
+
+    assert_eq!(world.query_entity::<EntityId>(first).get(), Some(first));
+    assert_eq!(
+        world
+            .query::<(&Position, (EntityId, Option<&Player>))>()
+            .iter()
+            .filter(|(_, (_, player))| player.is_some())
+            .map(|(_, (entity, _))| entity)
+            .collect::<Vec<_>>(),
+        vec![second]
+    );
+}
+
+#[test]
+fn query_state_sees_new_tables() {
+    let mut world = World::default();
+    world.spawn((Position(0),));
+
+    let mut state = world.query_state::<&Position, Without<Frozen>>();
+    assert_eq!(state.iter(&world).iter().count(), 1);
+
+    world.spawn((Position(1), Player));
+    world.spawn((Position(2), Frozen));
+    let mut positions: Vec<_> = state
+        .iter(&world)
+        .iter()
+        .map(|position| position.0)
+        .collect();
+    positions.sort();
+    assert_eq!(positions, vec![0, 1]);
+
+    let entity = world.spawn((Position(3), Enemy));
+    world.del(entity);
+    assert_eq!(state.iter(&world).iter().count(), 2);
+}
+
+#[test]
+fn query_state_borrows_components() {
+    let mut world = World::default();
+    world.spawn((Position(0),));
+
+    let mut state = world.query_state::<&mut Position, ()>();
+    let _positions = world.query::<&Position>();
+
+    assert!(state.try_iter(&world).is_err());
+}
+
+#[test]
+#[should_panic(expected = "different world")]
+fn query_state_is_tied_to_its_world() {
+    let world = World::default();
+    let other = World::default();
+
+    let mut state = world.query_state::<&Position, ()>();
+    state.iter(&other);
+}
+
+#[test]
+fn chunks_cover_every_table() {
+    let mut world = World::default();
+    let mut expected: Vec<_> = (0..10)
+        .map(|i| {
+            if i % 2 == 0 {
+                world.spawn((Position(i), Sprite(i as u8)))
+            } else {
+                world.spawn((Position(i), Mesh(i as u8)))
+            }
+        })
+        .collect();
+    world.spawn((Sprite(0),));
+
+    let mut entities = Vec::new();
+    let mut chunks = 0;
+    world
+        .query::<(&mut Position, Option<&Sprite>)>()
+        .for_each_chunk(|ids, (positions, sprites)| {
+            assert_eq!(ids.len(), positions.len());
+            if let Some(sprites) = sprites {
+                assert_eq!(sprites.len(), positions.len());
+            }
+            positions.iter_mut().for_each(|position| position.0 *= 10);
+            entities.extend_from_slice(ids);
+            chunks += 1;
+        });
+    assert_eq!(chunks, 2);
+
+    entities.sort();
+    expected.sort();
+    assert_eq!(entities, expected);
+
+    let mut positions: Vec<_> = world
+        .query::<&Position>()
+        .iter()
+        .map(|position| position.0)
+        .collect();
+    positions.sort();
+    assert_eq!(positions, (0..10).map(|i| i * 10).collect::<Vec<_>>());
+}
+
+#[test]
+fn chunks_continue_after_next() {
+    let mut world = World::default();
+    (0..5).for_each(|i| {
+        world.spawn((Position(i),));
+    });
+
+    let mut query = world.query::<(EntityId, &Position)>();
+    let mut iter = query.iter();
+    iter.next();
+    iter.next();
+
+    let mut seen = Vec::new();
+    iter.for_each_chunk(|ids, (same_ids, positions)| {
+        assert_eq!(ids, same_ids);
+        seen.extend(positions.iter().map(|position| position.0));
+    });
+    assert_eq!(seen, vec![2, 3, 4]);
+}
+
+#[test]
+fn par_query_visits_every_row_once() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let mut world = World::default();
+    // enough rows to be split across threads
+    (0..20_000).for_each(|i| {
+        if i % 3 == 0 {
+            world.spawn((Position(i), Player));
+        } else {
+            world.spawn((Position(i),));
+        }
+    });
+
+    let visited = AtomicUsize::new(0);
+    world
+        .par_query::<&mut Position>()
+        .batch_size(7)
+        .for_each(|position| {
+            position.0 += 1;
+            visited.fetch_add(1, Ordering::Relaxed);
+        });
+    assert_eq!(visited.load(Ordering::Relaxed), 20_000);
+
+    let mut positions: Vec<_> = world
+        .query::<&Position>()
+        .iter()
+        .map(|position| position.0)
+        .collect();
+    positions.sort();
+    assert_eq!(positions, (1..=20_000).collect::<Vec<_>>());
+
+    let players = AtomicUsize::new(0);
+    world
+        .par_query_filtered::<EntityId, With<Player>>()
+        .for_each(|_| {
+            players.fetch_add(1, Ordering::Relaxed);
+        });
+    assert_eq!(players.load(Ordering::Relaxed), 6667);
+}
+
+#[test]
+fn par_query_borrows_components() {
+    let mut world = World::default();
+    world.spawn((Position(0),));
+
+    let _positions = world.query::<&Position>();
+
+    assert!(world.try_par_query::<&mut Position>().is_err());
+    assert!(world.try_par_query::<&Position>().is_ok());
+}
+
+#[test]
+fn get_many_mut_hands_out_disjoint_items() {
+    let mut world = World::default();
+    let attacker = world.spawn((Position(10), Player));
+    let defender = world.spawn((Position(3),));
+
+    let [a, d] = world
+        .get_many_mut::<&mut Position, 2>([attacker, defender])
+        .unwrap();
+    d.0 -= a.0;
+    a.0 += 1;
+
+    assert_eq!(
+        world.query_entity::<&Position>(attacker).get(),
+        Some(&Position(11))
+    );
+    assert_eq!(
+        world.query_entity::<&Position>(defender).get(),
+        Some(&Position(-7))
+    );
+}
+
+#[test]
+fn get_many_mut_reports_bad_entities() {
+    let mut world = World::default();
+    let first = world.spawn((Position(0), Player));
+    let second = world.spawn((Position(1),));
+    let dead = world.spawn((Position(2), Player));
+    world.del(dead);
+
+    assert_eq!(
+        world.get_many_mut::<&mut Position, 2>([first, dead]).err(),
+        Some(QueryEntityError::NoSuchEntity(dead))
+    );
+    assert_eq!(
+        world
+            .get_many_mut::<&mut Position, 3>([first, second, first])
+            .err(),
+        Some(QueryEntityError::AliasedEntity(first))
+    );
+    assert_eq!(
+        world
+            .get_many_mut::<(&mut Position, &Player), 2>([first, second])
+            .err(),
+        Some(QueryEntityError::QueryDoesNotMatch(second))
+    );
+    assert!(matches!(
+        world.get_many_mut::<(&mut Position, &Position), 1>([first]),
+        Err(QueryEntityError::Borrow(_))
+    ));
+}
+
+#[test]
+fn added_and_changed_track_ticks() {
+    let mut world = World::default();
+    let a = world.spawn((Position(0),));
+    let b = world.spawn((Position(1),));
+
+    let added: Vec<_> = world
+        .query_filtered::<EntityId, Added<Position>>()
+        .iter()
+        .collect();
+    assert_eq!(added, vec![a, b]);
+
+    world.increment_change_tick();
+    assert_eq!(
+        world
+            .query_filtered::<EntityId, Added<Position>>()
+            .iter()
+            .count(),
+        0
+    );
+    assert_eq!(
+        world
+            .query_filtered::<EntityId, Changed<Position>>()
+            .iter()
+            .count(),
+        0
+    );
+
+    world.get_mut::<Position>(b).unwrap().0 += 1;
+    let c = world.spawn((Position(2),));
+    let changed: Vec<_> = world
+        .query_filtered::<EntityId, Changed<Position>>()
+        .iter()
+        .collect();
+    assert_eq!(changed, vec![b, c]);
+    let added: Vec<_> = world
+        .query_filtered::<EntityId, Added<Position>>()
+        .iter()
+        .collect();
+    assert_eq!(added, vec![c]);
+}
+
+#[test]
+fn mutable_queries_mark_changed() {
+    let mut world = World::default();
+    let a = world.spawn((Position(0),));
+    let b = world.spawn((Position(1),));
+    world.increment_change_tick();
+
+    // `Mut` only marks rows that are written through
+    world
+        .query::<(EntityId, Mut<Position>)>()
+        .iter()
+        .for_each(|(id, mut pos)| {
+            if id == a {
+                pos.0 += 1;
+            } else {
+                assert_eq!(pos.0, 1);
+            }
+        });
+    let changed: Vec<_> = world
+        .query_filtered::<EntityId, Changed<Position>>()
+        .iter()
+        .collect();
+    assert_eq!(changed, vec![a]);
+
+    world.increment_change_tick();
+    world.query::<&mut Position>().iter().for_each(|_| {});
+    let changed: Vec<_> = world
+        .query_filtered::<EntityId, Changed<Position>>()
+        .iter()
+        .collect();
+    assert_eq!(changed, vec![a, b]);
+}
+
+#[test]
+fn changed_survives_moves_between_tables() {
+    let mut world = World::default();
+    let a = world.spawn((Position(0),));
+    let b = world.spawn((Position(1),));
+    world.increment_change_tick();
+
+    world.get_mut::<Position>(b).unwrap().0 += 1;
+    world.insert(a, (Frozen,));
+    world.insert(b, (Frozen,));
+
+    let changed: Vec<_> = world
+        .query_filtered::<EntityId, Changed<Position>>()
+        .iter()
+        .collect();
+    assert_eq!(changed, vec![b]);
+    let added: Vec<_> = world
+        .query_filtered::<EntityId, Added<Frozen>>()
+        .iter()
+        .collect();
+    assert_eq!(added, vec![a, b]);
+}
+
+#[test]
+fn chunks_only_cover_changed_rows() {
+    let mut world = World::default();
+    let ids: Vec<_> = (0..6).map(|i| world.spawn((Position(i),))).collect();
+    world.increment_change_tick();
+
+    [1, 2, 4].iter().for_each(|idx| {
+        world.get_mut::<Position>(ids[*idx]).unwrap();
+    });
+
+    let mut chunks = Vec::new();
+    world
+        .query_filtered::<&Position, Changed<Position>>()
+        .for_each_chunk(|entities, positions| {
+            assert_eq!(entities.len(), positions.len());
+            chunks.push(entities.to_vec());
+        });
+    assert_eq!(chunks, vec![vec![ids[1], ids[2]], vec![ids[4]]]);
+}
+
+#[test]
+fn or_checks_rows_of_matching_filters() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let mut world = World::default();
+    let a = world.spawn((Position(0),));
+    let b = world.spawn((Position(1), Player));
+    world.spawn((Position(2),));
+    world.increment_change_tick();
+
+    world.get_mut::<Position>(a).unwrap();
+
+    let ids: Vec<_> = world
+        .query_filtered::<EntityId, Or<(Changed<Position>, With<Player>)>>()
+        .iter()
+        .collect();
+    assert_eq!(ids, vec![a, b]);
+
+    let visited = AtomicUsize::new(0);
+    world
+        .par_query_filtered::<EntityId, Changed<Position>>()
+        .for_each(|id| {
+            assert_eq!(id, a);
+            visited.fetch_add(1, Ordering::Relaxed);
+        });
+    assert_eq!(visited.load(Ordering::Relaxed), 1);
+}
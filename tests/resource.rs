@@ -0,0 +1,167 @@
+use std::{
+    marker::PhantomData,
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use mellow_ecs::{store::BorrowError, world::World};
+
+#[derive(Debug, PartialEq)]
+struct Time(u32);
+struct Position(i32);
+
+#[test]
+fn insert_replace_and_remove() {
+    let mut world = World::default();
+    assert!(world.resource::<Time>().is_none());
+
+    assert_eq!(world.insert_resource(Time(1)), None);
+    assert_eq!(world.insert_resource(Time(2)), Some(Time(1)));
+    assert!(world.contains_resource::<Time>());
+    assert_eq!(*world.resource::<Time>().unwrap(), Time(2));
+
+    world.resource_mut::<Time>().unwrap().0 += 1;
+    assert_eq!(world.remove_resource::<Time>(), Some(Time(3)));
+    assert_eq!(world.remove_resource::<Time>(), None);
+    assert!(!world.contains_resource::<Time>());
+}
+
+#[test]
+fn borrows_conflict() {
+    let mut world = World::default();
+    world.insert_resource(Time(0));
+
+    {
+        let _a = world.resource::<Time>();
+        let _b = world.resource::<Time>();
+        assert_eq!(
+            world.try_resource_mut::<Time>().err(),
+            Some(BorrowError::ResourceAlreadyBorrowed(std::any::type_name::<
+                Time,
+            >()))
+        );
+    }
+
+    {
+        let _time = world.resource_mut::<Time>();
+        assert_eq!(
+            world.try_resource::<Time>().err(),
+            Some(BorrowError::ResourceAlreadyBorrowedMut(
+                std::any::type_name::<Time>()
+            ))
+        );
+        assert!(world.try_resource_mut::<Time>().is_err());
+    }
+
+    assert!(world.try_resource_mut::<Time>().is_ok());
+}
+
+#[test]
+#[should_panic(expected = "resource")]
+fn conflicting_borrow_panics() {
+    let mut world = World::default();
+    world.insert_resource(Time(0));
+
+    let _time = world.resource_mut::<Time>();
+    world.resource::<Time>();
+}
+
+#[test]
+fn resources_and_queries_together() {
+    let mut world = World::default();
+    world.insert_resource(Time(2));
+    (0..3).for_each(|i| {
+        world.spawn((Position(i),));
+    });
+
+    let mut time = world.resource_mut::<Time>().unwrap();
+    for pos in world.query::<&mut Position>().iter() {
+        pos.0 += time.0 as i32;
+        time.0 += 1;
+    }
+    drop(time);
+
+    let positions: Vec<_> = world.query::<&Position>().iter().map(|pos| pos.0).collect();
+    assert_eq!(positions, [2, 4, 6]);
+    assert_eq!(*world.resource::<Time>().unwrap(), Time(5));
+}
+
+static WINDOW_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+// a handle that is neither `Send` nor `Sync`
+struct Window(PhantomData<*const ()>);
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        WINDOW_DROPS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn non_send_on_owner_thread() {
+    let mut world = World::default();
+    assert_eq!(world.non_send_resource_owner::<Rc<u32>>(), None);
+
+    world.insert_non_send_resource(Rc::new(1u32));
+    assert!(world.contains_non_send_resource::<Rc<u32>>());
+    assert!(!world.contains_resource::<u32>());
+    assert_eq!(
+        world.non_send_resource_owner::<Rc<u32>>(),
+        Some(thread::current().id())
+    );
+
+    assert_eq!(**world.non_send_resource::<Rc<u32>>().unwrap(), 1);
+    *world.non_send_resource_mut::<Rc<u32>>().unwrap() = Rc::new(2);
+    {
+        let _rc = world.non_send_resource::<Rc<u32>>();
+        assert!(world.try_non_send_resource_mut::<Rc<u32>>().is_err());
+    }
+
+    assert_eq!(
+        world.remove_non_send_resource::<Rc<u32>>(),
+        Some(Rc::new(2))
+    );
+    assert!(!world.contains_non_send_resource::<Rc<u32>>());
+}
+
+#[test]
+fn non_send_panics_on_other_threads() {
+    let mut world = World::default();
+    world.insert_non_send_resource(Rc::new(1u32));
+
+    thread::scope(|scope| {
+        let result = scope
+            .spawn(|| {
+                world.non_send_resource::<Rc<u32>>();
+            })
+            .join();
+        assert!(result.is_err());
+
+        let result = scope
+            .spawn(|| {
+                world.non_send_resource_mut::<Rc<u32>>();
+            })
+            .join();
+        assert!(result.is_err());
+    });
+
+    // the failed attempts don't hold on to the borrow
+    assert!(world.try_non_send_resource_mut::<Rc<u32>>().is_ok());
+}
+
+#[test]
+fn non_send_is_leaked_when_dropped_on_other_thread() {
+    let mut world = World::default();
+    world.insert_non_send_resource(Window(PhantomData));
+    world.insert_resource(Time(0));
+
+    let result = thread::spawn(move || drop(world)).join();
+    assert!(result.is_err());
+    assert_eq!(WINDOW_DROPS.load(Ordering::Relaxed), 0);
+
+    let mut world = World::default();
+    world.insert_non_send_resource(Window(PhantomData));
+    drop(world);
+    assert_eq!(WINDOW_DROPS.load(Ordering::Relaxed), 1);
+}
@@ -0,0 +1,99 @@
+use std::{
+    mem::ManuallyDrop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use mellow_ecs::store::{ItemType, Store};
+
+fn push<T: 'static + Send + Sync>(store: &mut Store, value: T) -> usize {
+    let value = ManuallyDrop::new(value);
+    unsafe { store.push((&*value as *const T).cast(), 0, 0) }
+}
+
+fn get<T: 'static + Send + Sync>(store: &Store, idx: usize) -> &T {
+    unsafe { &*store.get(idx).unwrap().cast::<T>() }
+}
+
+#[test]
+fn capacity_grows_geometrically() {
+    let mut store = Store::new(ItemType::of::<u64>());
+    assert_eq!((store.len(), store.capacity()), (0, 0));
+
+    let capacities: Vec<_> = (0..9u64)
+        .map(|i| {
+            assert_eq!(push(&mut store, i), i as usize);
+            store.capacity()
+        })
+        .collect();
+    assert_eq!(capacities, [4, 4, 4, 4, 8, 8, 8, 8, 16]);
+
+    store.reserve(100);
+    assert_eq!(store.capacity(), 109);
+    store.reserve(100);
+    assert_eq!(store.capacity(), 109);
+
+    assert_eq!(store.len(), 9);
+    assert_eq!(store.added_ticks().len(), 9);
+    assert_eq!(store.changed_ticks().len(), 9);
+    assert!((0..9).all(|i| *get::<u64>(&store, i) == i as u64));
+    assert!(store.get(9).is_none());
+}
+
+#[test]
+fn shrinks_to_zero_and_grows_again() {
+    let mut store = Store::new(ItemType::of::<String>());
+    (0..5).for_each(|i| {
+        push(&mut store, i.to_string());
+    });
+
+    store.shrink_to_fit();
+    assert_eq!(store.capacity(), 5);
+
+    store.clear();
+    assert!(store.is_empty());
+    assert_eq!(store.capacity(), 5);
+
+    store.shrink_to_fit();
+    assert_eq!(store.capacity(), 0);
+
+    push(&mut store, String::from("again"));
+    assert_eq!(store.capacity(), 4);
+    assert_eq!(get::<String>(&store, 0), "again");
+}
+
+#[test]
+#[should_panic(expected = "capacity overflow")]
+fn reserve_overflow_panics() {
+    let mut store = Store::new(ItemType::of::<u32>());
+    push(&mut store, 1u32);
+    store.reserve(usize::MAX);
+}
+
+static ZST_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+struct Zst;
+
+impl Drop for Zst {
+    fn drop(&mut self) {
+        ZST_DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn zero_sized_items_never_allocate() {
+    let mut store = Store::new(ItemType::of::<Zst>());
+    assert_eq!(store.capacity(), usize::MAX);
+
+    (0..1000).for_each(|_| {
+        push(&mut store, Zst);
+    });
+    store.reserve(1000);
+    store.shrink_to_fit();
+    assert_eq!(store.len(), 1000);
+    assert_eq!(store.capacity(), usize::MAX);
+    assert!(store.get(999).is_some());
+
+    store.clear();
+    assert_eq!(ZST_DROPS.load(Ordering::SeqCst), 1000);
+    assert_eq!(store.capacity(), usize::MAX);
+}
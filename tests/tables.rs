@@ -0,0 +1,59 @@
+use mellow_ecs::world::World;
+
+#[derive(Debug, PartialEq)]
+struct Health(u32);
+#[derive(Debug, PartialEq)]
+struct Stunned;
+#[derive(Debug, PartialEq)]
+struct Shield(u32);
+
+// every non-empty table holding the component yields one chunk
+fn tables_with<T: 'static + Send + Sync>(world: &World) -> usize {
+    let mut tables = 0;
+    world.query::<&T>().for_each_chunk(|_, _| tables += 1);
+    tables
+}
+
+#[test]
+fn insert_then_remove_returns_to_the_original_table() {
+    let mut world = World::default();
+    let first = world.spawn((Health(1),));
+    let second = world.spawn((Health(2),));
+
+    // the second round goes through the cached edges
+    for _ in 0..2 {
+        world.insert(second, (Stunned,));
+        assert_eq!(tables_with::<Health>(&world), 2);
+
+        assert_eq!(world.remove::<(Stunned,)>(second), Some((Stunned,)));
+        assert_eq!(tables_with::<Health>(&world), 1);
+    }
+
+    assert_eq!(world.get::<Health>(first).as_deref(), Some(&Health(1)));
+    assert_eq!(world.get::<Health>(second).as_deref(), Some(&Health(2)));
+}
+
+#[test]
+fn partially_overlapping_bundles() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(1), Stunned));
+    world.spawn((Stunned,));
+
+    // overwrites the health and adds the shield
+    world.insert(entity, (Health(5), Shield(3)));
+    assert_eq!(world.get::<Health>(entity).as_deref(), Some(&Health(5)));
+    assert_eq!(world.get::<Shield>(entity).as_deref(), Some(&Shield(3)));
+    assert!(world.has::<Stunned>(entity));
+    assert_eq!(tables_with::<Stunned>(&world), 2);
+
+    // the insert only added the shield, so removing the whole bundle must
+    // not lead back to the entity's first table
+    assert_eq!(
+        world.remove::<(Health, Shield)>(entity),
+        Some((Health(5), Shield(3)))
+    );
+    assert!(!world.has::<Health>(entity));
+    assert!(world.has::<Stunned>(entity));
+    assert_eq!(tables_with::<Stunned>(&world), 1);
+    assert_eq!(world.remove::<(Health, Shield)>(entity), None);
+}
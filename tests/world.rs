@@ -0,0 +1,303 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use mellow_ecs::{
+    entity::EntityId,
+    query::With,
+    world::{RemovalMode, World},
+};
+
+#[derive(Debug, PartialEq)]
+struct Health(u32);
+#[derive(Debug, PartialEq)]
+struct Stunned;
+
+// every non-empty table holding the component yields one chunk
+fn tables_with<T: 'static + Send + Sync>(world: &World) -> usize {
+    let mut tables = 0;
+    world.query::<&T>().for_each_chunk(|_, _| tables += 1);
+    tables
+}
+
+#[test]
+fn get_and_get_mut() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(10),));
+
+    assert_eq!(world.get::<Health>(entity).as_deref(), Some(&Health(10)));
+    assert_eq!(world.get::<Stunned>(entity).as_deref(), None);
+
+    world.get_mut::<Health>(entity).unwrap().0 -= 3;
+    assert_eq!(world.get::<Health>(entity).as_deref(), Some(&Health(7)));
+
+    world.del(entity);
+    assert_eq!(world.get::<Health>(entity).as_deref(), None);
+    assert!(world.get_mut::<Health>(entity).is_none());
+}
+
+#[test]
+fn contains_and_has() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(10),));
+
+    assert!(world.contains(entity));
+    assert!(world.has::<Health>(entity));
+    assert!(!world.has::<Stunned>(entity));
+
+    world.insert(entity, (Stunned,));
+    assert!(world.has::<Stunned>(entity));
+
+    world.del(entity);
+    assert!(!world.contains(entity));
+    assert!(!world.has::<Health>(entity));
+}
+
+#[test]
+#[should_panic(expected = "already borrowed mutably")]
+fn get_respects_query_borrows() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(10),));
+
+    let _health = world.query::<&mut Health>();
+    world.get::<Health>(entity);
+}
+
+#[test]
+fn get_borrows_until_dropped() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(10),));
+
+    let health = world.get::<Health>(entity).unwrap();
+    assert!(world.try_query::<&mut Health>().is_err());
+    assert!(world.try_query::<&Health>().is_ok());
+    assert_eq!(health.0, 10);
+
+    drop(health);
+    let _health = world.query::<&mut Health>();
+    assert!(world.try_get::<Health>(entity).is_err());
+    assert!(matches!(world.try_get::<Stunned>(entity), Ok(None)));
+}
+
+#[test]
+fn entity_ref() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(10), Stunned));
+
+    let entity_ref = world.entity(entity).unwrap();
+    assert_eq!(entity_ref.id(), entity);
+    assert_eq!(entity_ref.get::<Health>().as_deref(), Some(&Health(10)));
+    assert!(entity_ref.has::<Stunned>());
+
+    let mut names: Vec<_> = entity_ref.archetype().iter().map(|typ| typ.name).collect();
+    names.sort_unstable();
+    assert_eq!(
+        names,
+        [
+            std::any::type_name::<Health>(),
+            std::any::type_name::<Stunned>()
+        ]
+    );
+
+    world.del(entity);
+    assert!(world.entity(entity).is_none());
+}
+
+#[test]
+fn entity_ref_get_borrows_until_dropped() {
+    let mut world = World::default();
+    let entity = world.spawn((Health(10),));
+
+    let entity_ref = world.entity(entity).unwrap();
+    let health = entity_ref.get::<Health>().unwrap();
+    assert!(world.try_query::<&mut Health>().is_err());
+
+    drop(health);
+    let _health = world.query::<&mut Health>();
+    assert!(entity_ref.try_get::<Health>().is_err());
+}
+
+#[test]
+fn bundles_with_duplicate_components_are_rejected() {
+    let mut world = World::default();
+    let entity = world.spawn((Stunned,));
+
+    let spawn = panic::catch_unwind(AssertUnwindSafe(|| world.spawn((Health(1), Health(2)))));
+    assert!(spawn.is_err());
+    let insert = panic::catch_unwind(AssertUnwindSafe(|| {
+        world.insert(entity, (Health(1), Health(2)));
+    }));
+    assert!(insert.is_err());
+
+    let other = world.spawn((Health(3),));
+    assert_eq!(world.get::<Health>(other).as_deref(), Some(&Health(3)));
+    assert!(!world.has::<Health>(entity));
+    assert_eq!(world.query::<EntityId>().iter().count(), 2);
+}
+
+#[test]
+fn entity_mut_chained_edits() {
+    let mut world = World::default();
+    let other = world.spawn((Health(1),));
+    let entity = world.spawn((Health(10),));
+
+    let mut entity_mut = world.entity_mut(entity).unwrap();
+    entity_mut.insert((Stunned,));
+    entity_mut.get_mut::<Health>().unwrap().0 += 5;
+    assert_eq!(entity_mut.get::<Health>(), Some(&Health(15)));
+    assert_eq!(entity_mut.archetype().len(), 2);
+
+    assert_eq!(entity_mut.remove::<(Stunned,)>(), Some((Stunned,)));
+    assert_eq!(entity_mut.remove::<(Stunned,)>(), None);
+    assert!(!entity_mut.has::<Stunned>());
+    assert_eq!(entity_mut.get::<Health>(), Some(&Health(15)));
+
+    entity_mut.despawn();
+    assert!(!world.contains(entity));
+    assert_eq!(world.get::<Health>(other).as_deref(), Some(&Health(1)));
+}
+
+#[test]
+fn removals_are_logged_until_cleared() {
+    let mut world = World::default();
+    let a = world.spawn((Health(10), Stunned));
+    let b = world.spawn((Health(5),));
+
+    world.remove::<(Stunned,)>(a);
+    world.del(b);
+    assert_eq!(world.removed::<Stunned>(), [a]);
+    assert_eq!(world.removed::<Health>(), [b]);
+
+    world.clear_removed();
+    assert!(world.removed::<Stunned>().is_empty());
+    assert!(world.removed::<Health>().is_empty());
+
+    let c = world.spawn((Health(1),));
+    world.entity_mut(a).unwrap().despawn();
+    world.clear();
+    assert_eq!(world.removed::<Health>(), [a, c]);
+    assert!(world.removed::<u32>().is_empty());
+}
+
+#[test]
+fn removals_only_last_a_tick() {
+    let mut world = World::default();
+
+    for _ in 0..100 {
+        let entity = world.spawn((Health(1), Stunned));
+        world.remove::<(Stunned,)>(entity);
+        world.del(entity);
+        assert_eq!(world.removed::<Health>(), [entity]);
+        assert_eq!(world.removed::<Stunned>(), [entity]);
+
+        world.increment_change_tick();
+        assert!(world.removed::<Health>().is_empty());
+        assert!(world.removed::<Stunned>().is_empty());
+    }
+}
+
+#[test]
+fn ordered_removal_keeps_iteration_order() {
+    let mut world = World::default();
+    world.set_removal_mode(RemovalMode::Ordered);
+    let entities: Vec<_> = (0..6).map(|i| world.spawn((Health(i), Stunned))).collect();
+
+    world.del(entities[1]);
+    world.remove::<(Stunned,)>(entities[3]);
+
+    let healths: Vec<_> = world
+        .query_filtered::<&Health, With<Stunned>>()
+        .iter()
+        .map(|health| health.0)
+        .collect();
+    assert_eq!(healths, [0, 2, 4, 5]);
+}
+
+#[test]
+fn locations_survive_removals() {
+    for mode in [RemovalMode::Swap, RemovalMode::Ordered] {
+        let mut world = World::default();
+        world.set_removal_mode(mode);
+        let entities: Vec<_> = (0..8).map(|i| world.spawn((Health(i),))).collect();
+
+        world.del(entities[0]);
+        world.del(entities[5]);
+        world.insert(entities[2], (Stunned,));
+        world.remove::<(Health,)>(entities[7]);
+
+        for (i, entity) in entities.iter().enumerate() {
+            let expected = match i {
+                0 | 5 | 7 => None,
+                _ => Some(Health(i as u32)),
+            };
+            let health = world.get::<Health>(*entity);
+            assert_eq!(health.as_deref(), expected.as_ref(), "{:?}", mode);
+        }
+        assert!(world.has::<Stunned>(entities[2]));
+        assert!(world.contains(entities[7]));
+    }
+}
+
+#[test]
+fn freed_slots_are_reused_with_a_new_generation() {
+    let mut world = World::default();
+    let stale = world.spawn((Health(1),));
+    assert!(world.del(stale));
+
+    let entity = world.spawn((Health(2),));
+    assert_eq!(entity.index(), stale.index());
+    assert_eq!(entity.generation(), stale.generation() + 1);
+
+    assert!(!world.contains(stale));
+    assert!(world.query_entity::<&Health>(stale).get().is_none());
+    assert!(!world.del(stale));
+    assert_eq!(world.get::<Health>(entity).as_deref(), Some(&Health(2)));
+}
+
+#[test]
+fn entity_ids_round_trip_through_bits() {
+    let mut world = World::default();
+    let first = world.spawn((Health(1),));
+    world.del(first);
+    let second = world.spawn((Health(2),));
+
+    for entity in [first, second] {
+        assert_eq!(EntityId::from_bits(entity.to_bits()), entity);
+    }
+    assert_ne!(first.to_bits(), second.to_bits());
+}
+
+#[test]
+fn bundle_order_does_not_matter() {
+    let mut world = World::default();
+    let first = world.spawn((Health(1), Stunned));
+    let second = world.spawn((Stunned, Health(2)));
+
+    assert_eq!(tables_with::<Health>(&world), 1);
+    assert_eq!(world.get::<Health>(first).as_deref(), Some(&Health(1)));
+    assert_eq!(world.get::<Health>(second).as_deref(), Some(&Health(2)));
+}
+
+#[test]
+fn locations_follow_rows_of_a_large_table() {
+    let mut world = World::default();
+    let entities: Vec<_> = (0..1000)
+        .map(|i| world.spawn((Health(i), Stunned)))
+        .collect();
+
+    // deleting from the front keeps moving the last rows around
+    entities.iter().step_by(3).for_each(|entity| {
+        world.del(*entity);
+    });
+    entities.iter().skip(1).step_by(7).for_each(|entity| {
+        world.remove::<(Stunned,)>(*entity);
+    });
+
+    for (i, entity) in entities.iter().enumerate() {
+        let health = world.get::<Health>(*entity);
+        if i % 3 == 0 {
+            assert!(health.is_none());
+        } else {
+            assert_eq!(health.as_deref(), Some(&Health(i as u32)));
+            assert_eq!(world.has::<Stunned>(*entity), (i - 1) % 7 != 0);
+        }
+    }
+}